@@ -0,0 +1,26 @@
+use nano::app::App;
+
+#[derive(PartialEq, Eq, Hash)]
+pub struct StartEvent;
+
+/// Loads the plugin of `fixtures/greeter_plugin`, build it first with `cargo build -p greeter_plugin`.
+/// `tests/dynamic_plugins.rs` builds it on its own
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or("target/debug/libgreeter_plugin.so".to_string());
+
+    let mut app = App::new();
+    // SAFETY: the plugin is built from this workspace with the same compiler and features
+    let plugin = unsafe { app.load_plugin(&path) }.expect("Could not load the plugin");
+    println!("Loaded {}", plugin.name());
+
+    // Removes its startup system and its global, it can then be loaded again
+    app.unload_plugin(plugin);
+    println!("Unloaded {}", plugin.name());
+    // SAFETY: same library as above
+    unsafe { app.load_plugin(&path) }.expect("Could not load the plugin again");
+
+    app.run(StartEvent)
+        .expect("A system errored during the run");
+}
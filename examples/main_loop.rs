@@ -0,0 +1,45 @@
+use nano::{
+    access,
+    globals::{Globals, IntoSingletonKey, Singleton},
+    systems::{GlobalAccess, Scheduler},
+};
+
+#[derive(PartialEq, Eq, Hash)]
+pub struct TickEvent {
+    frame: u32,
+}
+
+pub struct Position {
+    x: f32,
+}
+
+/// Drives the scheduler from our own loop instead of `Scheduler::run`
+fn main() {
+    let mut scheduler = Scheduler::new();
+    scheduler.on_type::<TickEvent, _>(on_tick);
+
+    let mut globals = Globals::new();
+    globals.insert(Singleton(Position { x: 0. }));
+
+    let mut runtime = scheduler.into_runtime(globals);
+    for frame in 0..5 {
+        // Poll the window, the network...
+        runtime.push_event(TickEvent { frame });
+        runtime
+            .run_until_idle()
+            .expect("A system errored during the frame");
+
+        let x = runtime.globals().get(Position::SINGLETON).unwrap().x;
+        println!("Frame {frame}: x = {x}");
+    }
+
+    runtime.into_globals();
+}
+
+fn on_tick(g: GlobalAccess, event: &TickEvent) {
+    access! { g |
+        &mut position: Position::SINGLETON,
+    };
+
+    position.x += event.frame as f32 * 0.5;
+}
@@ -0,0 +1,72 @@
+use nano::{
+    access,
+    app::{App, Plugin, PluginId},
+    globals::{IntoSingletonKey, Singleton},
+    systems::GlobalAccess,
+};
+
+#[derive(PartialEq, Eq, Hash)]
+pub struct TickEvent;
+
+pub struct Time {
+    frame: u32,
+}
+
+pub struct Score {
+    points: u32,
+}
+
+pub struct TimePlugin;
+
+impl Plugin for TimePlugin {
+    fn build(&self, app: &mut App) {
+        app.globals.insert(Singleton(Time { frame: 0 }));
+        app.scheduler.on(TickEvent, on_tick).label("time");
+    }
+}
+
+/// Needs the `Time` global of the `TimePlugin`
+pub struct ScorePlugin {
+    points_per_frame: u32,
+}
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        let points_per_frame = self.points_per_frame;
+        app.globals.insert(Singleton(Score { points: 0 }));
+        app.scheduler
+            .on(TickEvent, move |g: GlobalAccess| {
+                access! { g |
+                    &time: Time::SINGLETON,
+                    &mut score: Score::SINGLETON,
+                };
+                score.points += time.frame * points_per_frame;
+            })
+            .after("time");
+    }
+
+    fn dependencies(&self) -> Vec<PluginId> {
+        vec![PluginId::of::<TimePlugin>()]
+    }
+}
+
+fn main() {
+    let mut app = App::new();
+    // Added before its dependency, it is still built after it
+    app.add_plugin(ScorePlugin {
+        points_per_frame: 10,
+    })
+    .add_plugin(TimePlugin);
+
+    let output = app.run(TickEvent).expect("A system errored during the run");
+    let score = output.globals.get(Score::SINGLETON).unwrap().points;
+    println!("Score: {score}");
+}
+
+fn on_tick(g: GlobalAccess) {
+    access! { g |
+        &mut time: Time::SINGLETON,
+    };
+
+    time.frame += 1;
+}
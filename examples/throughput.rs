@@ -0,0 +1,63 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use nano::{
+    access,
+    events::EventQueue,
+    globals::{Globals, IntoSingletonKey},
+    systems::{GlobalAccess, Scheduler},
+};
+
+const SYSTEMS: usize = 5_000;
+const TICKS: usize = 200;
+
+#[derive(PartialEq, Eq, Hash)]
+pub struct TickEvent;
+
+/// Dispatches `TICKS` times `SYSTEMS` tiny systems bound to the same event
+/// and prints how many systems ran per second
+/// The number of worker threads can be given as the first argument
+fn main() {
+    let mut builder = Scheduler::builder();
+    if let Some(threads) = std::env::args().nth(1) {
+        builder = builder.threads(threads.parse().expect("Invalid number of threads"));
+    }
+    let mut scheduler = builder.build();
+    let ran = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..SYSTEMS {
+        let ran = ran.clone();
+        scheduler.on(TickEvent, move |_: GlobalAccess| {
+            ran.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    let ticks = Arc::new(AtomicUsize::new(1));
+    scheduler.on(TickEvent, move |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+
+        if ticks.fetch_add(1, Ordering::Relaxed) < TICKS {
+            event_queue.push(TickEvent);
+        }
+    });
+
+    let start = Instant::now();
+    scheduler
+        .run(TickEvent, Globals::new())
+        .expect("A system errored during the run");
+    let elapsed = start.elapsed();
+
+    let ran = ran.load(Ordering::Relaxed);
+    assert_eq!(ran, SYSTEMS * TICKS);
+    println!(
+        "{ran} systems in {elapsed:?} ({:.0} systems/s)",
+        ran as f64 / elapsed.as_secs_f64()
+    );
+}
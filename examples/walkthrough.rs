@@ -11,6 +11,11 @@ pub struct StartEvent;
 #[derive(PartialEq, Eq, Hash)]
 pub struct SayHelloEvent;
 
+#[derive(PartialEq, Eq, Hash)]
+pub struct SayGoodbyeEvent {
+    times: u32,
+}
+
 pub struct User {
     name: String,
 }
@@ -28,8 +33,11 @@ fn main() {
 
     scheduler.on(StartEvent, on_start);
     scheduler.on(SayHelloEvent, on_say_hello);
+    scheduler.on_type::<SayGoodbyeEvent, _>(on_say_goodbye);
 
-    scheduler.run(StartEvent, globals);
+    scheduler
+        .run(StartEvent, globals)
+        .expect("A system errored during the run");
 }
 
 fn on_start(g: GlobalAccess) {
@@ -43,6 +51,7 @@ fn on_start(g: GlobalAccess) {
     }));
 
     event_queue.push(SayHelloEvent);
+    event_queue.push(SayGoodbyeEvent { times: 3 });
 }
 
 fn on_say_hello(g: GlobalAccess) {
@@ -53,3 +62,13 @@ fn on_say_hello(g: GlobalAccess) {
 
     println!("{}", language.hello_msg.replace("{}", &user.name));
 }
+
+fn on_say_goodbye(g: GlobalAccess, event: &SayGoodbyeEvent) {
+    access! { g |
+        &user: User::SINGLETON,
+    };
+
+    for _ in 0..event.times {
+        println!("Goodbye {} !", user.name);
+    }
+}
@@ -0,0 +1,44 @@
+use nano::{
+    access,
+    app::{App, Plugin},
+    export_plugin,
+    globals::{IntoSingletonKey, Singleton},
+    systems::GlobalAccess,
+};
+
+pub struct Greeting(pub String);
+
+/// The greetings of a thread, moved to the `Vec<String>` singleton of the host at every sync point
+#[derive(Default)]
+pub struct Greeted(Vec<String>);
+
+pub struct GreeterPlugin;
+
+impl Plugin for GreeterPlugin {
+    fn build(&self, app: &mut App) {
+        app.globals
+            .insert(Singleton(Greeting("Hello from a plugin !".to_string())));
+        app.globals.insert_per_thread(Greeted::default);
+        app.scheduler
+            .fold_at_sync(|log: &mut Vec<String>, greeted: Greeted| log.extend(greeted.0));
+        app.scheduler.on_startup(greet);
+        app.scheduler.on_type::<String, _>(greet_name);
+    }
+}
+
+fn greet(g: GlobalAccess) {
+    let mut greeted = g.per_thread::<Greeted>().unwrap();
+    access! { g |
+        &greeting: Greeting::SINGLETON,
+    };
+
+    println!("{}", greeting.0);
+    greeted.0.push(greeting.0.clone());
+}
+
+fn greet_name(g: GlobalAccess, name: &String) {
+    let mut greeted = g.per_thread::<Greeted>().unwrap();
+    greeted.0.push(format!("Hello {name} !"));
+}
+
+export_plugin!(GreeterPlugin);
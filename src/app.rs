@@ -0,0 +1,253 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    hash::{Hash, Hasher},
+};
+
+use any_key::AnyHash;
+
+use crate::{
+    errors::RunError,
+    events::Events,
+    globals::{Globals, Singleton},
+    ordering::{self, Constraints},
+    runtime::{RunOutput, SchedulerRuntime},
+    systems::Scheduler,
+};
+
+/// Bundles the globals extensions, the initial globals and the systems of a subsystem
+/// so that they are added to an `App` in one call
+pub trait Plugin: Any {
+    fn build(&self, app: &mut App);
+
+    /// The plugins that have to be built before this one, e.g. `vec![PluginId::of::<PhysicsPlugin>()]`
+    /// They have to be added to the `App` too
+    fn dependencies(&self) -> Vec<PluginId> {
+        Vec::new()
+    }
+
+    fn name(&self) -> &'static str {
+        type_name::<Self>()
+    }
+}
+
+/// Identifies a plugin by its type, an `App` can only contain one plugin of each type.
+/// Only the type is compared, the name is for display
+#[derive(Debug, Clone, Copy)]
+pub struct PluginId {
+    pub(crate) type_id: TypeId,
+    pub(crate) name: &'static str,
+}
+
+impl PluginId {
+    pub fn of<P: Plugin>() -> Self {
+        Self {
+            type_id: TypeId::of::<P>(),
+            name: type_name::<P>(),
+        }
+    }
+
+    pub(crate) fn of_dyn(plugin: &dyn Plugin) -> Self {
+        Self {
+            type_id: (plugin as &dyn Any).type_id(),
+            name: plugin.name(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl PartialEq for PluginId {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_id == other.type_id
+    }
+}
+
+impl Eq for PluginId {}
+
+impl Hash for PluginId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&self.type_id, state);
+    }
+}
+
+type Plugins = Vec<Box<dyn Plugin>>;
+
+/// A `Scheduler` and its `Globals`, filled by plugins
+/// The plugins are built once the app runs, or on `build_plugins`, dependencies first
+pub struct App {
+    pub scheduler: Scheduler,
+    pub globals: Globals,
+    pub(crate) pending: Plugins,
+    pub(crate) built: Vec<PluginId>,
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) dynamic: Vec<crate::dynamic::DynamicPlugin>,
+}
+
+impl App {
+    pub fn new() -> Self {
+        Self::with_scheduler(Scheduler::new())
+    }
+
+    /// Uses a scheduler configured with `Scheduler::builder`
+    pub fn with_scheduler(scheduler: Scheduler) -> Self {
+        Self {
+            scheduler,
+            globals: Globals::new(),
+            pending: Vec::new(),
+            built: Vec::new(),
+            #[cfg(feature = "dynamic-plugins")]
+            dynamic: Vec::new(),
+        }
+    }
+
+    /// Plugins can also be added from another plugin's `build`
+    /// Panics if a plugin of the same type was already added
+    pub fn add_plugin(&mut self, plugin: impl Plugin) -> &mut Self {
+        let id = PluginId::of_dyn(&plugin);
+        if self.has_plugin(id) {
+            panic!("Plugin {} was added twice!", id.name);
+        }
+        self.pending.push(Box::new(plugin));
+        self
+    }
+
+    /// Inserts the `Events<T>` singleton and swaps its buffers at the end of every step,
+    /// see `Scheduler::update_events`
+    pub fn add_events<T: Send + Sync + 'static>(&mut self) -> &mut Self {
+        self.globals.insert(Singleton(Events::<T>::new()));
+        self.scheduler.update_events::<T>();
+        self
+    }
+
+    pub fn has_plugin(&self, id: PluginId) -> bool {
+        self.built.contains(&id)
+            || self
+                .pending
+                .iter()
+                .any(|plugin| PluginId::of_dyn(&**plugin) == id)
+    }
+
+    /// Builds the plugins added since the last call, each after its dependencies.
+    /// A plugin whose dependency is missing waits until another plugin's `build` adds it.
+    /// Panics if a dependency is still missing once nothing else can be built,
+    /// or if the dependencies contain a cycle
+    pub fn build_plugins(&mut self) -> &mut Self {
+        while !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            let (ready, waiting) = self.split_ready(pending);
+            if ready.is_empty() {
+                self.panic_missing_dependency(&waiting);
+            }
+            // Still pending so that the plugins built meanwhile see them
+            self.pending = waiting;
+
+            let constraints: Vec<Constraints<TypeId>> = ready
+                .iter()
+                .map(|plugin| self.constraints(&**plugin))
+                .collect();
+            let layers = ordering::layers(ready.into_iter().zip(&constraints).collect())
+                .expect("Plugin dependencies contain a cycle!");
+
+            for plugin in layers.into_iter().flatten() {
+                self.built.push(PluginId::of_dyn(&*plugin));
+                plugin.build(self);
+            }
+        }
+        self
+    }
+
+    /// Splits the plugins between the ones whose dependencies are built or ready too,
+    /// and the ones waiting for a dependency that wasn't added yet
+    fn split_ready(&self, mut ready: Plugins) -> (Plugins, Plugins) {
+        let mut waiting = Vec::new();
+        loop {
+            let ids: Vec<PluginId> = ready
+                .iter()
+                .map(|plugin| PluginId::of_dyn(&**plugin))
+                .collect();
+            let (next, blocked): (Vec<_>, Vec<_>) = ready.into_iter().partition(|plugin| {
+                plugin
+                    .dependencies()
+                    .iter()
+                    .all(|dependency| self.built.contains(dependency) || ids.contains(dependency))
+            });
+            ready = next;
+            if blocked.is_empty() {
+                return (ready, waiting);
+            }
+            waiting.extend(blocked);
+        }
+    }
+
+    fn panic_missing_dependency(&self, waiting: &[Box<dyn Plugin>]) -> ! {
+        let ids: Vec<PluginId> = waiting
+            .iter()
+            .map(|plugin| PluginId::of_dyn(&**plugin))
+            .collect();
+        for plugin in waiting {
+            for dependency in plugin.dependencies() {
+                if !self.built.contains(&dependency) && !ids.contains(&dependency) {
+                    panic!(
+                        "Plugin {} depends on {} which wasn't added!",
+                        plugin.name(),
+                        dependency.name
+                    );
+                }
+            }
+        }
+        unreachable!("A plugin only waits for a missing dependency or a plugin waiting too")
+    }
+
+    /// Orders the plugin after its dependencies that aren't built yet
+    fn constraints(&self, plugin: &dyn Plugin) -> Constraints<TypeId> {
+        let id = PluginId::of_dyn(plugin);
+        let mut constraints = Constraints {
+            labels: vec![id.type_id],
+            ..Default::default()
+        };
+        for dependency in plugin.dependencies() {
+            if !self.built.contains(&dependency) {
+                constraints.after.push(dependency.type_id);
+            }
+        }
+        constraints
+    }
+
+    /// Builds the plugins and gives the scheduler and its globals back
+    pub fn into_parts(mut self) -> (Scheduler, Globals) {
+        self.build_plugins();
+        #[cfg(feature = "dynamic-plugins")]
+        self.dynamic
+            .drain(..)
+            .for_each(crate::dynamic::DynamicPlugin::leak);
+        (self.scheduler, self.globals)
+    }
+
+    pub fn into_runtime(self) -> SchedulerRuntime {
+        let (scheduler, globals) = self.into_parts();
+        scheduler.into_runtime(globals)
+    }
+
+    /// See `Scheduler::run`
+    pub fn run<T: AnyHash + Send + Sync>(self, start_event: T) -> Result<RunOutput, RunError> {
+        let (scheduler, globals) = self.into_parts();
+        scheduler.run(start_event, globals)
+    }
+
+    /// See `Scheduler::run_until_exit`
+    pub fn run_until_exit<T: AnyHash + Send + Sync>(
+        self,
+        start_event: T,
+    ) -> Result<RunOutput, RunError> {
+        let (scheduler, globals) = self.into_parts();
+        scheduler.run_until_exit(start_event, globals)
+    }
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,34 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::rng::Rng;
+
+/// Random delays injected by a chaos scheduler before systems lock globals
+pub(crate) struct Chaos {
+    seed: u64,
+    max_delay: Duration,
+    delays: AtomicU64, // Number of delays drawn so far, mixed with the seed
+}
+
+impl Chaos {
+    pub fn new(seed: u64, max_delay: Duration) -> Self {
+        Self {
+            seed,
+            max_delay,
+            delays: AtomicU64::new(0),
+        }
+    }
+
+    pub fn delay(&self) {
+        let max_nanos = self.max_delay.as_nanos() as u64;
+        if max_nanos == 0 {
+            return;
+        }
+        let nth = self.delays.fetch_add(1, Ordering::Relaxed);
+        let nanos = Rng::new(self.seed ^ nth.rotate_left(32)).next_u64() % max_nanos;
+        thread::sleep(Duration::from_nanos(nanos));
+    }
+}
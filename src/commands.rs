@@ -0,0 +1,89 @@
+use any_key::AnyHash;
+
+use crate::systems::{IntoSystem, Phase, Scheduler, SystemId, SystemIds};
+
+/// How a run requested by a system stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExitMode {
+    /// The current batch of events is finished, the events pushed meanwhile are not processed
+    Graceful,
+    /// No other event is dispatched, the systems already running are waited for
+    Immediate,
+}
+
+pub(crate) enum SchedulerCommand {
+    Exit(ExitMode),
+    Remove(SystemId),
+    Disable(SystemId),
+    Enable(SystemId),
+    Register(Box<dyn FnOnce(&mut Scheduler) + Send + Sync>),
+}
+
+/// Lets systems control the scheduler, available as `Scheduler::COMMANDS`
+/// The commands are applied at the next sync point, like the `Globals::COMMANDS`
+pub struct SchedulerCommandQueue {
+    pub(crate) commands: Vec<SchedulerCommand>,
+    ids: SystemIds,
+}
+
+impl SchedulerCommandQueue {
+    pub fn new_empty() -> Self {
+        Self {
+            commands: Vec::new(),
+            ids: SystemIds::new(),
+        }
+    }
+
+    /// Stops the run once the current batch of events is processed
+    pub fn exit(&mut self) {
+        self.commands
+            .push(SchedulerCommand::Exit(ExitMode::Graceful))
+    }
+
+    /// Stops the run before dispatching another event, the remaining events are dropped
+    pub fn exit_now(&mut self) {
+        self.commands
+            .push(SchedulerCommand::Exit(ExitMode::Immediate))
+    }
+
+    /// See `Scheduler::on`, the returned id can already be used by the next commands
+    pub fn on<E: AnyHash + Send + Sync, T>(
+        &mut self,
+        event: E,
+        sys: impl IntoSystem<T, E>,
+    ) -> SystemId {
+        let id = self.ids.next();
+        let system = sys.into_system();
+        self.commands
+            .push(SchedulerCommand::Register(Box::new(move |scheduler| {
+                scheduler.insert_system(id, Phase::Main, event, system)
+            })));
+        id
+    }
+
+    /// See `Scheduler::on_type`
+    pub fn on_type<E: AnyHash + Send + Sync, T>(&mut self, sys: impl IntoSystem<T, E>) -> SystemId {
+        let id = self.ids.next();
+        let system = sys.into_system();
+        self.commands
+            .push(SchedulerCommand::Register(Box::new(move |scheduler| {
+                scheduler.insert_typed_system::<E>(id, system)
+            })));
+        id
+    }
+
+    /// See `Scheduler::remove`
+    pub fn remove(&mut self, id: SystemId) {
+        self.commands.push(SchedulerCommand::Remove(id))
+    }
+
+    /// See `Scheduler::disable`
+    pub fn disable(&mut self, id: SystemId) {
+        self.commands.push(SchedulerCommand::Disable(id))
+    }
+
+    /// See `Scheduler::enable`
+    pub fn enable(&mut self, id: SystemId) {
+        self.commands.push(SchedulerCommand::Enable(id))
+    }
+}
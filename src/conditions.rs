@@ -0,0 +1,57 @@
+use std::{ops::Not, sync::Arc};
+
+use crate::{
+    globals::{IntoGlobalKey, Singleton},
+    systems::GlobalAccess,
+};
+
+/// Decides whether a system runs, checked right before the system is sent to the thread pool
+/// so a skipped system never takes a worker. See `SystemConfig::run_if`
+/// Conditions should only read the globals and be cheap, they are checked on every dispatch.
+/// Cloning a condition shares it, they can be combined with `and`, `or` and `!`
+#[derive(Clone)]
+pub struct Condition(Arc<dyn Fn(GlobalAccess) -> bool + Send + Sync>);
+
+impl Condition {
+    pub fn new(condition: impl Fn(GlobalAccess) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(condition))
+    }
+
+    /// True while the `Singleton<T>` global exists
+    pub fn singleton_exists<T: 'static>() -> Self {
+        Self::new(|g| {
+            let key = IntoGlobalKey::into(Singleton::<T>::key());
+            g.inner_may_deadlock.id_of(key).is_some()
+        })
+    }
+
+    /// `other` is only checked if this condition is true
+    pub fn and(self, other: impl Into<Condition>) -> Self {
+        let other = other.into();
+        Self::new(move |g| self.check(g) && other.check(g))
+    }
+
+    /// `other` is only checked if this condition is false
+    pub fn or(self, other: impl Into<Condition>) -> Self {
+        let other = other.into();
+        Self::new(move |g| self.check(g) || other.check(g))
+    }
+
+    pub(crate) fn check(&self, g: GlobalAccess) -> bool {
+        (self.0)(g)
+    }
+}
+
+impl Not for Condition {
+    type Output = Condition;
+
+    fn not(self) -> Self::Output {
+        Self::new(move |g| !self.check(g))
+    }
+}
+
+impl<F: Fn(GlobalAccess) -> bool + Send + Sync + 'static> From<F> for Condition {
+    fn from(condition: F) -> Self {
+        Self::new(condition)
+    }
+}
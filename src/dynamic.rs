@@ -0,0 +1,247 @@
+use std::{
+    any::TypeId,
+    ffi::{c_char, c_void, CStr, CString},
+    fmt,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+};
+
+use crate::{
+    app::{App, Plugin, PluginId},
+    globals::GlobalEntryId,
+    per_thread::PerThreadEntry,
+    systems::SystemId,
+};
+
+/// Checked against the version of nano the host was built with when loading a plugin,
+/// the compiler and the features of nano can't be checked, see `App::load_plugin`
+pub const PLUGIN_ABI_VERSION: &CStr =
+    match CStr::from_bytes_with_nul(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()) {
+        Ok(version) => version,
+        Err(_) => panic!("Invalid plugin ABI version!"),
+    };
+
+const VERSION_SYMBOL: &CStr = c"nano_plugin_abi_version";
+const CREATE_SYMBOL: &CStr = c"nano_plugin_create";
+
+/// Exports the plugin from a `cdylib` crate so that `App::load_plugin` can load it
+/// ```ignore
+/// nano::export_plugin!(GreeterPlugin { name: "Johny" });
+/// ```
+#[macro_export]
+macro_rules! export_plugin {
+    ($plugin:expr) => {
+        #[no_mangle]
+        pub extern "C" fn nano_plugin_abi_version() -> *const ::std::ffi::c_char {
+            $crate::dynamic::PLUGIN_ABI_VERSION.as_ptr()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn nano_plugin_create() -> *mut ::std::ffi::c_void {
+            let plugin: ::std::boxed::Box<dyn $crate::app::Plugin> =
+                ::std::boxed::Box::new($plugin);
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new(plugin)).cast()
+        }
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginLoadError {
+    /// The library could not be opened, with the message of the dynamic loader
+    Open(String),
+    /// The library doesn't export a plugin with `export_plugin!`
+    MissingSymbol(&'static str),
+    /// The plugin was built against another version of nano
+    IncompatibleVersion { expected: String, found: String },
+    /// A plugin of the same type is already in the `App`
+    AlreadyAdded(String),
+}
+
+impl fmt::Display for PluginLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open(message) => write!(f, "could not open plugin library: {message}"),
+            Self::MissingSymbol(symbol) => write!(f, "plugin library doesn't export {symbol}"),
+            Self::IncompatibleVersion { expected, found } => write!(
+                f,
+                "plugin was built against nano {found} but the host uses {expected}"
+            ),
+            Self::AlreadyAdded(name) => write!(f, "plugin {name} was already added"),
+        }
+    }
+}
+
+impl std::error::Error for PluginLoadError {}
+
+/// A plugin loaded with `App::load_plugin` and everything it added to the `App`
+pub(crate) struct DynamicPlugin {
+    id: PluginId,
+    plugins: Vec<PluginId>, // The plugin and the ones it added
+    systems: Vec<SystemId>,
+    globals: Vec<GlobalEntryId>,
+    per_thread: Vec<PerThreadEntry>,
+    key_types: Vec<TypeId>,
+    hooks: Vec<usize>,
+    library: Library, // Dropped last, the code of everything above lives in it
+}
+
+impl DynamicPlugin {
+    /// Keeps the library loaded for the rest of the process, once its systems and globals
+    /// have left the `App` they can't be tracked anymore
+    pub fn leak(self) {
+        std::mem::forget(self.library);
+    }
+}
+
+impl App {
+    /// Loads a plugin exported with `export_plugin!` from a shared library and builds it right away,
+    /// along with the plugins added before. The library stays loaded until the plugin is unloaded,
+    /// or for the rest of the process once the app runs
+    ///
+    /// # Safety
+    /// The library must export a plugin with `export_plugin!` and be built by the same compiler
+    /// as the host, against the same nano with the same features: the layouts of the `App`
+    /// and the `TypeId`s of the plugin must match the ones of the host.
+    /// Loading the library runs its initializers
+    pub unsafe fn load_plugin(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<PluginId, PluginLoadError> {
+        self.build_plugins();
+
+        let library = Library::open(path.as_ref())?;
+        // SAFETY: `export_plugin!` defines the symbol with this signature, it returns a pointer
+        // to a static nul-terminated string that lives as long as the library
+        let version = unsafe {
+            let version: extern "C" fn() -> *const c_char =
+                std::mem::transmute(library.symbol(VERSION_SYMBOL)?);
+            CStr::from_ptr(version())
+        };
+        if version != PLUGIN_ABI_VERSION {
+            return Err(PluginLoadError::IncompatibleVersion {
+                expected: PLUGIN_ABI_VERSION.to_string_lossy().into_owned(),
+                found: version.to_string_lossy().into_owned(),
+            });
+        }
+
+        // SAFETY: the versions match so the symbol comes from `export_plugin!` of the same nano,
+        // it hands over a leaked `Box<Box<dyn Plugin>>` that is reclaimed exactly once here
+        let plugin = unsafe {
+            let create: extern "C" fn() -> *mut c_void =
+                std::mem::transmute(library.symbol(CREATE_SYMBOL)?);
+            *Box::from_raw(create().cast::<Box<dyn Plugin>>())
+        };
+        // The name of the plugin lives in the library, it is copied so that the id outlives it
+        let id = PluginId {
+            name: Box::leak(plugin.name().into()),
+            ..PluginId::of_dyn(&*plugin)
+        };
+        if self.has_plugin(id) {
+            drop(plugin);
+            return Err(PluginLoadError::AlreadyAdded(id.name.to_string()));
+        }
+
+        let built = self.built.len();
+        let systems = self.scheduler.system_ids();
+        let globals = self.globals.entry_ids();
+        let per_thread = self.globals.per_thread_entries();
+        let key_types = self.globals.key_types();
+        let hooks = self.scheduler.hook_ids();
+
+        self.pending.push(plugin);
+        self.build_plugins();
+
+        self.dynamic.push(DynamicPlugin {
+            id,
+            plugins: self.built[built..].to_vec(),
+            systems: new_items(self.scheduler.system_ids(), &systems),
+            globals: new_items(self.globals.entry_ids(), &globals),
+            per_thread: new_items(self.globals.per_thread_entries(), &per_thread),
+            key_types: new_items(self.globals.key_types(), &key_types),
+            hooks: new_items(self.scheduler.hook_ids(), &hooks),
+            library,
+        });
+        Ok(id)
+    }
+
+    /// Removes the systems and globals added by a plugin loaded with `load_plugin`,
+    /// including the ones of the plugins it added, then closes its library.
+    /// Returns false if the plugin wasn't loaded from a library
+    pub fn unload_plugin(&mut self, id: PluginId) -> bool {
+        let Some(index) = self.dynamic.iter().position(|plugin| plugin.id == id) else {
+            return false;
+        };
+        let plugin = self.dynamic.remove(index);
+
+        for system in plugin.systems {
+            self.scheduler.remove(system);
+        }
+        for hook in plugin.hooks {
+            self.scheduler.remove_hook(hook);
+        }
+        for entry in plugin.globals {
+            self.globals.remove_entry(entry);
+        }
+        for entry in plugin.per_thread {
+            self.globals.remove_per_thread_entry(entry);
+        }
+        for key_type in plugin.key_types {
+            self.globals.remove_support_for(key_type);
+        }
+        self.built.retain(|built| !plugin.plugins.contains(built));
+        true
+    }
+}
+
+fn new_items<T: PartialEq>(after: Vec<T>, before: &[T]) -> Vec<T> {
+    after
+        .into_iter()
+        .filter(|item| !before.contains(item))
+        .collect()
+}
+
+/// Handle to a shared library opened with `dlopen`, closed on drop
+struct Library(*mut c_void);
+
+impl Library {
+    fn open(path: &Path) -> Result<Self, PluginLoadError> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| PluginLoadError::Open("path contains a nul byte".to_string()))?;
+        // SAFETY: `path` is a valid nul-terminated string, the handle is checked below
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(PluginLoadError::Open(last_error()));
+        }
+        Ok(Self(handle))
+    }
+
+    fn symbol(&self, name: &'static CStr) -> Result<*mut c_void, PluginLoadError> {
+        // SAFETY: the handle is open until `self` is dropped and `name` is nul-terminated
+        let symbol = unsafe { libc::dlsym(self.0, name.as_ptr()) };
+        if symbol.is_null() {
+            let name = name.to_str().unwrap();
+            return Err(PluginLoadError::MissingSymbol(name));
+        }
+        Ok(symbol)
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        // SAFETY: the handle was returned by `dlopen` and is closed only once,
+        // `DynamicPlugin` drops the library after everything that points into it
+        unsafe { libc::dlclose(self.0) };
+    }
+}
+
+fn last_error() -> String {
+    // SAFETY: `dlerror` has no preconditions
+    let error = unsafe { libc::dlerror() };
+    if error.is_null() {
+        return "unknown error".to_string();
+    }
+    // SAFETY: a non-null `dlerror` is a nul-terminated string valid until the next `dl*` call
+    unsafe { CStr::from_ptr(error) }
+        .to_string_lossy()
+        .into_owned()
+}
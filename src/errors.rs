@@ -0,0 +1,127 @@
+use std::{
+    any::Any,
+    fmt::{self, Debug},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use any_key::AnyHash;
+
+use crate::{
+    globals::Globals,
+    locals::LocalGlobals,
+    systems::{CustomSystemError, SystemId},
+};
+
+/// What the scheduler does when a system returns an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop dispatching events, wait for the running systems and return the errors
+    #[default]
+    Abort,
+    /// Keep running and return every collected error once the event queue is empty
+    Continue,
+    /// Push a `SystemErrored` event for each error, subscribe to it with `Scheduler::on_type`.
+    /// The errors of the `SystemErrored` systems are returned like with `Continue`
+    Emit,
+}
+
+/// An error returned by a system, along with the system and the event that produced it
+pub struct SystemError {
+    pub system: SystemId,
+    /// The type name of the system's function, closures of the same function share it
+    pub system_name: &'static str,
+    pub event: Arc<dyn AnyHash + Send + Sync>,
+    pub error: Box<dyn CustomSystemError>,
+}
+
+impl SystemError {
+    /// The panic message if the system panicked instead of returning an error
+    pub fn panic_message(&self) -> Option<&str> {
+        let error: &dyn Any = &*self.error;
+        error
+            .downcast_ref::<SystemPanic>()
+            .map(|panic| panic.message.as_str())
+    }
+}
+
+impl Debug for SystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SystemError")
+            .field("system", &self.system)
+            .field("system_name", &self.system_name)
+            .field("event", &self.event)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+/// Error reported in place of a system's result when it panicked
+#[derive(Debug)]
+pub struct SystemPanic {
+    pub message: String,
+}
+
+impl SystemPanic {
+    pub(crate) fn from_payload(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload
+                .downcast_ref::<&str>()
+                .map_or("Box<dyn Any>", |message| message)
+                .to_string(),
+        };
+        SystemPanic { message }
+    }
+}
+
+/// Returned by `Scheduler::run` when systems errored, the globals and locals are handed back as they were left
+pub struct RunError {
+    pub globals: Box<Globals>,
+    pub locals: LocalGlobals,
+    pub errors: Vec<SystemError>,
+}
+
+impl Debug for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunError")
+            .field("errors", &self.errors)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} system(s) errored", self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n{}: {:?}", error.system_name, error.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Event pushed for each system error when using `ErrorPolicy::Emit`
+/// Two `SystemErrored` are only equal if they wrap the same error
+#[derive(Clone)]
+pub struct SystemErrored(pub Arc<SystemError>);
+
+impl PartialEq for SystemErrored {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl Eq for SystemErrored {}
+
+impl Hash for SystemErrored {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&Arc::as_ptr(&self.0), state)
+    }
+}
+
+impl Debug for SystemErrored {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SystemErrored").field(&self.0).finish()
+    }
+}
@@ -1,7 +1,23 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
 use any_key::AnyHash;
 
+use crate::systems::{GlobalAccess, SharedEvent, SystemId};
+
+pub(crate) type BoxedEvent = Box<dyn AnyHash + Send + Sync>;
+
 pub struct EventQueue {
-    events: Vec<Box<dyn AnyHash + Send + Sync>>,
+    events: Vec<BoxedEvent>,
 }
 
 impl EventQueue {
@@ -13,8 +29,309 @@ impl EventQueue {
         self.events.push(Box::new(event))
     }
 
-    pub(crate) fn drain(&mut self) -> Vec<Box<dyn AnyHash + Send + Sync>> {
+    pub(crate) fn push_boxed(&mut self, event: BoxedEvent) {
+        self.events.push(event)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub(crate) fn drain(&mut self) -> Vec<BoxedEvent> {
         let new_cap = self.events.len() / 3 * 2;
         std::mem::replace(&mut self.events, Vec::with_capacity(new_cap))
     }
 }
+
+/// Typed events stored in the `Events::<T>::SINGLETON` global instead of the `EventQueue`,
+/// they don't trigger systems but are read by any number of them with `Events::read`,
+/// which keeps a read cursor per system, or with an `EventReader`.
+/// Register the type with `App::add_events` or `Scheduler::update_events` so that the buffers
+/// are swapped at the sync point ending every step: an event can be read until the end of the step
+/// after the one it was sent in, e.g. by the systems of the events it pushed.
+/// Swapping at every sync point would drop the events before the systems of the later events
+/// of the same step could read them
+pub struct Events<T> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    previous_start: usize, // Number of events sent before the first one of `previous`
+    sent: usize,
+    cursors: Mutex<HashMap<SystemId, usize>>, // Events read by each system, see `Events::read`
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+            sent: 0,
+            cursors: Mutex::default(),
+        }
+    }
+
+    /// The events sent since the system calling it last read them, each system reads
+    /// each event once. Outside of systems, every event still buffered
+    pub fn read(&self, g: &GlobalAccess) -> impl Iterator<Item = &T> {
+        let from = match g.system() {
+            Some(system) => self
+                .cursors
+                .lock()
+                .unwrap()
+                .insert(system, self.sent)
+                .unwrap_or(0),
+            None => 0,
+        };
+        self.since(from)
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+        self.sent += 1;
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        let len = self.current.len();
+        self.current.extend(events);
+        self.sent += self.current.len() - len;
+    }
+
+    /// Number of events that can still be read
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every event that can still be read, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(&self.current)
+    }
+
+    /// Drops the previous buffer and keeps the current one for one more update,
+    /// called at the end of every step once registered with `Scheduler::update_events`
+    pub fn update(&mut self) {
+        self.previous_start += self.previous.len();
+        self.previous.clear();
+        std::mem::swap(&mut self.previous, &mut self.current);
+        // A cursor before the buffered events reads the same as no cursor,
+        // so the ones of systems that stopped reading don't pile up
+        let previous_start = self.previous_start;
+        self.cursors
+            .get_mut()
+            .unwrap()
+            .retain(|_, read| *read > previous_start);
+    }
+
+    /// Drops every event, readers skip them
+    pub fn clear(&mut self) {
+        self.update();
+        self.update();
+    }
+
+    /// The events sent from the `from`th one on, from where it is still buffered
+    fn since(&self, from: usize) -> impl Iterator<Item = &T> {
+        let skipped = from.saturating_sub(self.previous_start);
+        let previous = self.previous.get(skipped..).unwrap_or_default();
+        let current = self
+            .current
+            .get(skipped.saturating_sub(self.previous.len())..)
+            .unwrap_or_default();
+        previous.iter().chain(current)
+    }
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Read cursor into an `Events<T>` owned by the reader, e.g. moved into a closure,
+/// when the cursor kept by `Events::read` for each system doesn't fit.
+/// Each event is read once, the ones dropped before the reader caught up are skipped
+pub struct EventReader<T> {
+    read: AtomicUsize,
+    _events: PhantomData<fn() -> T>,
+}
+
+impl<T> EventReader<T> {
+    /// Reads from the oldest event still buffered
+    pub fn new() -> Self {
+        Self {
+            read: AtomicUsize::new(0),
+            _events: PhantomData,
+        }
+    }
+
+    /// The events sent since the last read
+    pub fn read<'a>(&self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let from = self.read.swap(events.sent, Ordering::Relaxed);
+        events.since(from)
+    }
+
+    /// Number of events `read` would return
+    pub fn len(&self, events: &Events<T>) -> usize {
+        let from = self.read.load(Ordering::Relaxed);
+        events.sent.saturating_sub(from.max(events.previous_start))
+    }
+
+    pub fn is_empty(&self, events: &Events<T>) -> bool {
+        self.len(events) == 0
+    }
+
+    /// Marks every event as read
+    pub fn clear(&self, events: &Events<T>) {
+        self.read.store(events.sent, Ordering::Relaxed);
+    }
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stops the scheduler once processed, it can still trigger systems
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Exit;
+
+/// Event of the systems registered with `Scheduler::on_startup`, can't be pushed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Startup(pub(crate) ());
+
+/// Event of the systems registered with `Scheduler::on_shutdown`, can't be pushed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Shutdown(pub(crate) ());
+
+/// Pushed when a system cancels an event with `GlobalAccess::cancel_event`,
+/// subscribe to it with `Scheduler::on_type::<Cancelled<E>, _>`
+#[derive(Debug)]
+pub struct Cancelled<E>(pub Arc<E>);
+
+impl<E: AnyHash + Send + Sync> Cancelled<E> {
+    pub(crate) fn boxed(event: SharedEvent) -> BoxedEvent {
+        let event: Arc<dyn Any + Send + Sync> = event;
+        let event = event
+            .downcast()
+            .expect("Cancelled event should be of the registered type");
+        Box::new(Cancelled::<E>(event))
+    }
+
+    fn as_any_hash(&self) -> &(dyn AnyHash + Send + Sync) {
+        &*self.0
+    }
+}
+
+impl<E> Clone for Cancelled<E> {
+    fn clone(&self) -> Self {
+        Cancelled(self.0.clone())
+    }
+}
+
+impl<E: AnyHash + Send + Sync> PartialEq for Cancelled<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_any_hash() == other.as_any_hash()
+    }
+}
+impl<E: AnyHash + Send + Sync> Eq for Cancelled<E> {}
+
+impl<E: AnyHash + Send + Sync> Hash for Cancelled<E> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(self.as_any_hash(), state)
+    }
+}
+
+/// State shared by the systems handling the same event
+pub(crate) struct Dispatch {
+    pub event: SharedEvent,
+    cancelled: AtomicBool,
+}
+
+impl Dispatch {
+    pub fn new(event: SharedEvent) -> Self {
+        Self {
+            event,
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pushes events into a scheduler from any thread, even while it is running.
+/// The events are merged into the `EventQueue` at its next drain.
+/// Get one from `Scheduler::event_sender`, `SchedulerRuntime::event_sender`
+/// or from the `EventSender::SINGLETON` global inside systems
+#[derive(Clone)]
+pub struct EventSender {
+    inbox: Arc<Inbox>,
+}
+
+impl EventSender {
+    pub(crate) fn new(inbox: Arc<Inbox>) -> Self {
+        Self { inbox }
+    }
+
+    /// Also wakes up the scheduler if it is waiting for events
+    pub fn send<T: AnyHash + Send + Sync>(&self, event: T) {
+        self.inbox.events.lock().unwrap().push(Box::new(event));
+        self.inbox.received.notify_all();
+    }
+
+    /// Sends an `Exit` event
+    pub fn request_exit(&self) {
+        self.send(Exit)
+    }
+}
+
+/// Events sent from outside of the scheduler, waiting for the next drain
+#[derive(Default)]
+pub(crate) struct Inbox {
+    events: Mutex<Vec<BoxedEvent>>,
+    received: Condvar,
+}
+
+impl Inbox {
+    pub fn is_empty(&self) -> bool {
+        self.events.lock().unwrap().is_empty()
+    }
+
+    pub fn take(&self) -> Vec<BoxedEvent> {
+        std::mem::take(&mut self.events.lock().unwrap())
+    }
+
+    /// Blocks until an event is sent
+    pub fn wait(&self) {
+        let events = self.events.lock().unwrap();
+        drop(
+            self.received
+                .wait_while(events, |events| events.is_empty())
+                .unwrap(),
+        );
+    }
+
+    /// Returns false if no event was sent before the timeout
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let events = self.events.lock().unwrap();
+        let (events, _) = self
+            .received
+            .wait_timeout_while(events, timeout, |events| events.is_empty())
+            .unwrap();
+        !events.is_empty()
+    }
+}
@@ -5,6 +5,10 @@ use std::{
     marker::PhantomData,
 };
 
+#[cfg(feature = "dynamic-plugins")]
+use crate::per_thread::PerThreadEntry;
+use crate::per_thread::{PerThread, PerThreadGlobals};
+
 use dyn_clone::DynClone;
 use parking_lot::{
     MappedRwLockReadGuard, MappedRwLockWriteGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
@@ -24,6 +28,7 @@ pub struct Globals {
     accessors: HashMap<TypeId, Box<dyn KeyAccessor>>, // Key typeid to accessor
     entries: Vec<Option<RwLock<GlobalEntry>>>,
     free_entries: Vec<GlobalEntryId>,
+    per_thread: Box<PerThreadGlobals>, // Boxed as most globals have none
 }
 
 pub fn map_read_guard<'a, T: Any>(guard: RwLockReadGuard<'a, GlobalEntry>) -> GlobalRef<'a, T> {
@@ -45,6 +50,7 @@ impl Globals {
             accessors: HashMap::new(),
             entries: Vec::new(),
             free_entries: Vec::new(),
+            per_thread: Box::new(PerThreadGlobals::new()),
         };
         // extend Globals, adding singletons (1type=1key=1value)
         // needed by default
@@ -100,14 +106,80 @@ impl Globals {
     /// /!\ Can also not return because the backing globals isn't of type T::Value
     pub fn remove<T: IntoGlobalKey>(&mut self, key: T) -> Option<T::Value> {
         let id = self.id_of(key.into())?;
-        let Some(entry) = self.entries[id].take() else {
-            return None;
-        };
-        let entry = entry.into_inner();
-        for (tid, part) in entry.key.parts {
+        let entry = self.remove_entry(id)?;
+        Some(*(entry.value.downcast().ok()?))
+    }
+
+    /// Removes the entry along with all of its keys
+    pub(crate) fn remove_entry(&mut self, id: GlobalEntryId) -> Option<GlobalEntry> {
+        let mut entry = self.entries.get_mut(id)?.take()?.into_inner();
+        for (tid, part) in entry.key.parts.drain() {
             self.accessors.get_mut(&tid).unwrap().remove(part);
         }
-        Some(*(entry.value.downcast().ok()?))
+        Some(entry)
+    }
+
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) fn entry_ids(&self) -> Vec<GlobalEntryId> {
+        (0..self.entries.len())
+            .filter(|&id| self.entries[id].is_some())
+            .collect()
+    }
+
+    /// Key typeids supported through `add_support_for`
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) fn key_types(&self) -> Vec<TypeId> {
+        self.accessors.keys().copied().collect()
+    }
+
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) fn remove_support_for(&mut self, key_type: TypeId) {
+        self.accessors.remove(&key_type);
+    }
+
+    /// Gives every thread running systems its own instance of `T`, created with `init`,
+    /// e.g. for scratch buffers or RNGs. Systems get the instance of their thread
+    /// with `GlobalAccess::per_thread` without any lock. Replaces the previous instances
+    pub fn insert_per_thread<T: Send + 'static>(
+        &mut self,
+        init: impl Fn() -> T + Send + Sync + 'static,
+    ) {
+        self.per_thread.insert(init)
+    }
+
+    pub fn remove_per_thread<T: 'static>(&mut self) -> bool {
+        self.per_thread.remove::<T>()
+    }
+
+    /// Folds the instances of every thread, e.g. to sum per-thread counters.
+    /// Returns `init` if there is no per-thread `T`, see also `Scheduler::fold_at_sync`
+    pub fn fold_per_thread<T: Send + 'static, R>(
+        &mut self,
+        init: R,
+        f: impl FnMut(R, &mut T) -> R,
+    ) -> R {
+        match self.per_thread.get_mut::<T>() {
+            Some(per_thread) => per_thread.iter_mut().fold(init, f),
+            None => init,
+        }
+    }
+
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) fn per_thread_entries(&self) -> Vec<PerThreadEntry> {
+        self.per_thread.entries()
+    }
+
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) fn remove_per_thread_entry(&mut self, entry: PerThreadEntry) {
+        self.per_thread.remove_entry(entry)
+    }
+
+    pub(crate) fn per_thread_instances<T: Send + 'static>(&self) -> Option<&PerThread<T>> {
+        self.per_thread.get()
+    }
+
+    pub(crate) fn set_thread_slots(&mut self, slots: usize) {
+        self.per_thread.set_slots(slots)
     }
 
     pub fn id_of(&self, key: GlobalKey) -> Option<GlobalEntryId> {
@@ -126,25 +198,31 @@ impl Globals {
         id_found
     }
 
-    pub fn read_entry(&self, id: GlobalEntryId) -> Option<RwLockReadGuard<GlobalEntry>> {
+    pub fn read_entry(&self, id: GlobalEntryId) -> Option<RwLockReadGuard<'_, GlobalEntry>> {
         self.entries[id].as_ref()?.read().into()
     }
 
-    pub fn write_entry(&self, id: GlobalEntryId) -> Option<RwLockWriteGuard<GlobalEntry>> {
+    pub fn write_entry(&self, id: GlobalEntryId) -> Option<RwLockWriteGuard<'_, GlobalEntry>> {
         self.entries[id].as_ref()?.write().into()
     }
 
-    pub fn get<T: IntoGlobalKey>(&self, key: T) -> Option<GlobalRef<T::Value>> {
+    pub fn get<T: IntoGlobalKey>(&self, key: T) -> Option<GlobalRef<'_, T::Value>> {
         let id = self.id_of(key.into())?;
         Some(map_read_guard(self.entries[id].as_ref()?.read()))
     }
 
-    pub fn get_mut<T: IntoGlobalKey>(&self, key: T) -> Option<GlobalMut<T::Value>> {
+    pub fn get_mut<T: IntoGlobalKey>(&self, key: T) -> Option<GlobalMut<'_, T::Value>> {
         let id = self.id_of(key.into())?;
         Some(map_write_guard(self.entries[id].as_ref()?.write()))
     }
 }
 
+impl Default for Globals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct GlobalEntry {
     pub key: GlobalKey,
     pub value: Box<dyn Any + Send + Sync>,
@@ -255,7 +333,7 @@ impl GlobalsCommandQueue {
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 
-/// Singleton<T> Globals
+// Singleton<T> Globals
 
 pub struct SingletonGlobals;
 impl GlobalsExt for SingletonGlobals {
@@ -281,11 +359,11 @@ impl<T: Sized + 'static> IntoSingletonKey for T {
     const SINGLETON: SingletonKey<Self> = Singleton::<T>::key();
 }
 
-impl<T: 'static + Send + Sync> Into<GlobalEntry> for Singleton<T> {
-    fn into(self) -> GlobalEntry {
+impl<T: 'static + Send + Sync> From<Singleton<T>> for GlobalEntry {
+    fn from(singleton: Singleton<T>) -> Self {
         GlobalEntry {
-            key: <SingletonKey<T> as IntoGlobalKey>::into(Self::key()),
-            value: Box::new(self.0),
+            key: <SingletonKey<T> as IntoGlobalKey>::into(Singleton::<T>::key()),
+            value: Box::new(singleton.0),
         }
     }
 }
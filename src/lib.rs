@@ -1,11 +1,17 @@
+pub mod app;
+pub(crate) mod chaos;
+pub mod commands;
+pub mod conditions;
+#[cfg(feature = "dynamic-plugins")]
+pub mod dynamic;
+pub mod errors;
 pub mod events;
 pub mod globals;
+pub mod locals;
 pub mod macros;
+pub(crate) mod ordering;
+pub(crate) mod per_thread;
+pub(crate) mod rng;
+pub mod runtime;
 pub mod systems;
 pub(crate) mod threadpool;
-
-// TODO: Globals
-//   - Thread locals
-// TODO: Events system
-//   - Before/After events?
-//TODO: Module system (is it needed?)
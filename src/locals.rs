@@ -0,0 +1,74 @@
+use std::{
+    any::{Any, TypeId},
+    cell::{RefCell, RefMut},
+    collections::HashMap,
+    thread::{self, ThreadId},
+};
+
+/// Globals that don't have to be `Send` or `Sync`, e.g. FFI contexts or `Rc` caches
+/// They stay on the thread running the scheduler, only the systems registered with
+/// `SystemConfig::on_main_thread` can access them, through `GlobalAccess::locals`
+/// One value per type
+#[derive(Default)]
+pub struct LocalGlobals {
+    values: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl LocalGlobals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the previous value of this type
+    pub fn insert<T: 'static>(&mut self, value: T) -> Option<T> {
+        let previous = self.values.insert(TypeId::of::<T>(), Box::new(value))?;
+        Some(*previous.downcast().unwrap())
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        let value = self.values.remove(&TypeId::of::<T>())?;
+        Some(*value.downcast().unwrap())
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+}
+
+/// Handle to the locals of the thread running the scheduler, keeps `GlobalAccess` `Send` and `Sync`
+#[derive(Clone, Copy)]
+pub(crate) struct LocalsRef<'a> {
+    locals: &'a RefCell<LocalGlobals>,
+    owner: ThreadId,
+    // Created by the host, a plugin's copy of `std` numbers the threads on its own
+    current_thread: fn() -> ThreadId,
+}
+
+// SAFETY: the `RefCell` is only borrowed by `get`, which checks that it runs on the thread
+// owning the locals, and the returned `RefMut` can't leave that thread
+unsafe impl Send for LocalsRef<'_> {}
+unsafe impl Sync for LocalsRef<'_> {}
+
+impl<'a> LocalsRef<'a> {
+    /// The locals must belong to the calling thread
+    pub fn new(locals: &'a RefCell<LocalGlobals>) -> Self {
+        Self {
+            locals,
+            owner: thread::current().id(),
+            current_thread: || thread::current().id(),
+        }
+    }
+
+    /// `None` outside of the thread owning the locals
+    pub fn get(self) -> Option<RefMut<'a, LocalGlobals>> {
+        ((self.current_thread)() == self.owner).then(|| self.locals.borrow_mut())
+    }
+}
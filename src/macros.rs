@@ -1,15 +1,18 @@
-use std::any::Any;
-
 /// Allow to easily access globals.
 /// It also sorts globals by their id
 /// when locking them to avoid deadlocks.
 ///
 /// Syntax example:
 /// ```
+/// # use nano::{access, globals::IntoSingletonKey, systems::GlobalAccess};
+/// # struct User;
+/// # struct Language;
+/// # fn system(globals: GlobalAccess) {
 /// access! { globals |
 ///    &user: User::SINGLETON,
 ///    &mut language: Language::SINGLETON
 /// };
+/// # }
 /// ```
 #[macro_export]
 macro_rules! access {
@@ -42,7 +45,8 @@ macro_rules! access {
 
     (@drop $g:ident) => {drop($g);};
     ($g:ident | $($tail:tt)*) => {
-        let GlobalAccess { inner_may_deadlock } = $g;
+        $g.before_lock();
+        let $crate::systems::GlobalAccess { inner_may_deadlock, .. } = $g;
         let mut refids = Vec::new();
         let mut mutids = Vec::new();
         $crate::access!(@ids refids mutids inner_may_deadlock | , $($tail)* , );
@@ -0,0 +1,91 @@
+/// Labels of a system and the labels it has to run before or after,
+/// only the systems dispatched for the same event are ordered.
+/// Plugins are labeled by their `TypeId` instead
+#[derive(Clone)]
+pub(crate) struct Constraints<L = &'static str> {
+    pub labels: Vec<L>,
+    pub before: Vec<L>,
+    pub after: Vec<L>,
+}
+
+impl<L> Default for Constraints<L> {
+    fn default() -> Self {
+        Self {
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+}
+
+impl<L: PartialEq> Constraints<L> {
+    fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    fn has_label(&self, label: &L) -> bool {
+        self.labels.contains(label)
+    }
+}
+
+/// Splits the systems into layers, each system only depends on systems of the previous layers
+/// so every layer can run in parallel. The registration order is kept inside a layer.
+/// Returns `None` if the constraints contain a cycle
+pub(crate) fn layers<T, L: PartialEq>(systems: Vec<(T, &Constraints<L>)>) -> Option<Vec<Vec<T>>> {
+    if systems
+        .iter()
+        .all(|(_, constraints)| constraints.is_empty())
+    {
+        let layer: Vec<T> = systems.into_iter().map(|(system, _)| system).collect();
+        return Some(if layer.is_empty() {
+            vec![]
+        } else {
+            vec![layer]
+        });
+    }
+
+    // dependencies[i] are the systems that have to run before system i
+    let dependencies: Vec<Vec<usize>> = systems
+        .iter()
+        .enumerate()
+        .map(|(i, (_, constraints))| {
+            (0..systems.len())
+                .filter(|&j| j != i)
+                .filter(|&j| {
+                    let other = systems[j].1;
+                    constraints.after.iter().any(|label| other.has_label(label))
+                        || other
+                            .before
+                            .iter()
+                            .any(|label| constraints.has_label(label))
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut layer_of: Vec<Option<usize>> = vec![None; systems.len()];
+    let mut layer_count = 0;
+    while layer_of.iter().any(Option::is_none) {
+        let ready: Vec<usize> = (0..systems.len())
+            .filter(|&i| layer_of[i].is_none())
+            .filter(|&i| {
+                dependencies[i]
+                    .iter()
+                    .all(|&j| layer_of[j].is_some_and(|layer| layer < layer_count))
+            })
+            .collect();
+        if ready.is_empty() {
+            return None;
+        }
+        for i in ready {
+            layer_of[i] = Some(layer_count);
+        }
+        layer_count += 1;
+    }
+
+    let mut layers: Vec<Vec<T>> = (0..layer_count).map(|_| Vec::new()).collect();
+    for ((system, _), layer) in systems.into_iter().zip(layer_of) {
+        layers[layer.unwrap()].push(system);
+    }
+    Some(layers)
+}
@@ -0,0 +1,136 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use atomic_refcell::{AtomicRefCell, AtomicRefMut};
+
+/// One instance of a value per thread of the thread pool, plus one for the other threads.
+/// Worker `id` uses slot `id + 1`, the thread running the scheduler slot 0.
+/// Systems get the slot of their thread from their `GlobalAccess`, a thread local
+/// wouldn't work in a plugin loaded from a shared library, it has its own copy
+pub(crate) struct PerThread<T> {
+    slots: Vec<Slot<T>>,
+    init: Box<dyn Fn() -> T + Send + Sync>,
+}
+
+/// A slot is only ever borrowed mutably, so `T` never has to be shared between threads
+struct Slot<T>(AtomicRefCell<T>);
+
+// SAFETY: `Slot` never gives out a `&T` that could be used from two threads at once,
+// the `AtomicRefCell` hands out a single `&mut T` at a time which only requires `T: Send`
+unsafe impl<T: Send> Sync for Slot<T> {}
+
+impl<T: Send + 'static> PerThread<T> {
+    pub fn new(slots: usize, init: impl Fn() -> T + Send + Sync + 'static) -> Self {
+        let mut per_thread = Self {
+            slots: Vec::new(),
+            init: Box::new(init),
+        };
+        per_thread.resize(slots);
+        per_thread
+    }
+
+    /// The instance of the thread using this slot, panics if the thread already borrows it
+    pub fn get(&self, slot: usize) -> AtomicRefMut<'_, T> {
+        self.slots
+            .get(slot)
+            .unwrap_or_else(|| panic!("No per-thread slot for thread slot {slot}!"))
+            .0
+            .borrow_mut()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().map(|slot| slot.0.get_mut())
+    }
+
+    /// Same as `iter_mut` when no system is running, panics if a thread borrows its instance
+    pub fn for_each(&self, mut f: impl FnMut(&mut T)) {
+        for slot in &self.slots {
+            f(&mut slot.0.borrow_mut());
+        }
+    }
+}
+
+/// Identifies an inserted per-thread global, replacing it gives another id
+#[cfg(feature = "dynamic-plugins")]
+pub(crate) type PerThreadEntry = (TypeId, usize);
+
+/// The per-thread globals, by value typeid, along with the number of insertions before them
+pub(crate) struct PerThreadGlobals {
+    instances: HashMap<TypeId, (usize, Box<dyn AnyPerThread>)>,
+    slots: usize,
+    inserted: usize,
+}
+
+impl PerThreadGlobals {
+    pub fn new() -> Self {
+        Self {
+            instances: HashMap::new(),
+            slots: 1,
+            inserted: 0,
+        }
+    }
+
+    pub fn insert<T: Send + 'static>(&mut self, init: impl Fn() -> T + Send + Sync + 'static) {
+        let per_thread = PerThread::new(self.slots, init);
+        self.instances
+            .insert(TypeId::of::<T>(), (self.inserted, Box::new(per_thread)));
+        self.inserted += 1;
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> bool {
+        self.instances.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    pub fn get<T: Send + 'static>(&self) -> Option<&PerThread<T>> {
+        let (_, per_thread) = self.instances.get(&TypeId::of::<T>())?;
+        (&**per_thread as &dyn Any).downcast_ref()
+    }
+
+    pub fn get_mut<T: Send + 'static>(&mut self) -> Option<&mut PerThread<T>> {
+        let (_, per_thread) = self.instances.get_mut(&TypeId::of::<T>())?;
+        (&mut **per_thread as &mut dyn Any).downcast_mut()
+    }
+
+    #[cfg(feature = "dynamic-plugins")]
+    pub fn entries(&self) -> Vec<PerThreadEntry> {
+        self.instances
+            .iter()
+            .map(|(type_id, (inserted, _))| (*type_id, *inserted))
+            .collect()
+    }
+
+    /// Does nothing if the per-thread global was replaced since
+    #[cfg(feature = "dynamic-plugins")]
+    pub fn remove_entry(&mut self, (type_id, inserted): PerThreadEntry) {
+        if self
+            .instances
+            .get(&type_id)
+            .is_some_and(|(other, _)| *other == inserted)
+        {
+            self.instances.remove(&type_id);
+        }
+    }
+
+    /// Makes room for the instances of the thread pool's workers
+    pub fn set_slots(&mut self, slots: usize) {
+        self.slots = self.slots.max(slots);
+        for (_, per_thread) in self.instances.values_mut() {
+            per_thread.resize(self.slots);
+        }
+    }
+}
+
+/// Type erased `PerThread` so that they can be resized once the thread pool is known
+trait AnyPerThread: Any + Send + Sync {
+    fn resize(&mut self, slots: usize);
+}
+
+impl<T: Send + 'static> AnyPerThread for PerThread<T> {
+    fn resize(&mut self, slots: usize) {
+        while self.slots.len() < slots {
+            self.slots.push(Slot(AtomicRefCell::new((self.init)())));
+        }
+    }
+}
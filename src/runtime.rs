@@ -0,0 +1,438 @@
+use std::{
+    any::Any,
+    cell::{Ref, RefCell},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use any_key::AnyHash;
+use atomic_refcell::{AtomicRef, AtomicRefCell, AtomicRefMut};
+
+use crate::{
+    commands::{ExitMode, SchedulerCommand, SchedulerCommandQueue},
+    errors::{ErrorPolicy, SystemError, SystemErrored},
+    events::{Dispatch, EventQueue, EventSender, Exit, Shutdown, Startup},
+    globals::{Globals, Singleton},
+    locals::LocalGlobals,
+    rng::Rng,
+    systems::{DispatchOrder, GlobalAccess, GlobalsCell, Scheduler, SharedEvent, Subscriber},
+    threadpool::ThreadPool,
+};
+
+/// A started `Scheduler`, it owns the globals and the thread pool
+/// so that it can be driven step by step from another main loop
+pub struct SchedulerRuntime {
+    scheduler: Scheduler,
+    globals_cell: GlobalsCell,
+    thread_pool: ThreadPool,
+    dispatch_rng: Option<Rng>,
+    chaos_rng: Option<Rng>,
+    locals: RefCell<LocalGlobals>,
+    exit: Option<ExitMode>,
+    started: bool,
+    stopped: bool,
+}
+
+/// Why a run of the scheduler returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The event queue is empty
+    Idle,
+    /// An `Exit` event was processed (graceful) or a system asked to exit through `Scheduler::COMMANDS`
+    Exit(ExitMode),
+    /// No exit was requested before the timeout
+    TimedOut,
+}
+
+/// Returned by `Scheduler::run` and its variants
+pub struct RunOutput {
+    pub globals: Globals,
+    /// Given to `Scheduler::run_with_locals`, empty for the other variants
+    pub locals: LocalGlobals,
+    pub exit: ExitReason,
+}
+
+impl SchedulerRuntime {
+    pub(crate) fn new(scheduler: Scheduler, mut globals: Globals) -> Self {
+        globals.insert(Singleton(EventQueue::new()));
+        globals.insert(Singleton(scheduler.event_sender()));
+        globals.insert(Singleton(SchedulerCommandQueue::new_empty()));
+        let globals_cell: GlobalsCell = Arc::new(AtomicRefCell::new(globals));
+        let thread_pool = ThreadPool::new(&globals_cell, &scheduler.pool_config);
+        globals_cell
+            .borrow_mut()
+            .set_thread_slots(thread_pool.thread_slots());
+
+        let dispatch_rng = match scheduler.dispatch_order {
+            DispatchOrder::Registration => None,
+            DispatchOrder::Shuffled { seed } => Some(Rng::new(seed)),
+        };
+        let chaos_rng = scheduler.chaos_seed.map(|seed| Rng::new(!seed));
+
+        Self {
+            scheduler,
+            globals_cell,
+            thread_pool,
+            dispatch_rng,
+            chaos_rng,
+            locals: RefCell::default(),
+            exit: None,
+            started: false,
+            stopped: false,
+        }
+    }
+
+    /// No system runs between steps, so the globals can be accessed freely
+    pub fn globals(&self) -> AtomicRef<'_, Globals> {
+        self.globals_cell.borrow()
+    }
+
+    pub fn globals_mut(&mut self) -> AtomicRefMut<'_, Globals> {
+        self.globals_cell.borrow_mut()
+    }
+
+    /// The non-`Send` globals of the main thread systems, see `SystemConfig::on_main_thread`
+    pub fn locals(&self) -> Ref<'_, LocalGlobals> {
+        self.locals.borrow()
+    }
+
+    pub fn locals_mut(&mut self) -> &mut LocalGlobals {
+        self.locals.get_mut()
+    }
+
+    pub fn push_event<T: AnyHash + Send + Sync>(&mut self, event: T) {
+        self.with_event_queue(|event_queue| event_queue.push(event));
+    }
+
+    pub fn event_sender(&self) -> EventSender {
+        self.scheduler.event_sender()
+    }
+
+    /// Systems can be registered, removed, disabled or enabled between steps,
+    /// the thread pool and dispatch settings can't be changed anymore
+    pub fn scheduler_mut(&mut self) -> &mut Scheduler {
+        &mut self.scheduler
+    }
+
+    pub fn has_pending_events(&self) -> bool {
+        !self.scheduler.inbox.is_empty()
+            || self.with_event_queue(|event_queue| !event_queue.is_empty())
+    }
+
+    /// Blocks until there are events to process, e.g. sent by another thread
+    pub fn wait_for_events(&self) {
+        if !self.has_pending_events() {
+            self.scheduler.inbox.wait();
+        }
+    }
+
+    /// Returns false if there are still no events to process after the timeout
+    pub fn wait_for_events_timeout(&self, timeout: Duration) -> bool {
+        self.has_pending_events() || self.scheduler.inbox.wait_timeout(timeout)
+    }
+
+    /// Whether an exit was requested during the last run or step
+    pub fn exit_requested(&self) -> Option<ExitMode> {
+        self.exit
+    }
+
+    /// Processes the events currently in the queue, events pushed meanwhile are left for the next step.
+    /// Returns the errors of this step, with `ErrorPolicy::Abort` the remaining events of the batch are dropped
+    pub fn step(&mut self) -> Result<(), Vec<SystemError>> {
+        self.startup()?;
+        let mut errors = Vec::new();
+
+        let mut events = self.with_event_queue(EventQueue::drain);
+        events.extend(self.scheduler.inbox.take());
+        if let Some(rng) = &mut self.chaos_rng {
+            rng.shuffle(&mut events);
+        }
+
+        for event in events.into_iter().map(SharedEvent::from) {
+            if (&*event as &dyn Any).is::<Exit>() {
+                self.request_exit(ExitMode::Graceful);
+            }
+            if self.dispatch(event, &mut errors) {
+                break;
+            }
+        }
+
+        if self.exit == Some(ExitMode::Immediate) {
+            self.with_event_queue(EventQueue::drain);
+            self.scheduler.inbox.take();
+        }
+        self.scheduler.run_step_hooks(&self.globals_cell.borrow());
+
+        into_result(errors)
+    }
+
+    /// Runs the `Scheduler::on_startup` systems, done by the first step if not called before
+    pub fn startup(&mut self) -> Result<(), Vec<SystemError>> {
+        if std::mem::replace(&mut self.started, true) {
+            return Ok(());
+        }
+        self.run_stage(Startup(()))
+    }
+
+    /// Runs the `Scheduler::on_shutdown` systems, done by `into_globals` if not called before
+    pub fn shutdown(&mut self) -> Result<(), Vec<SystemError>> {
+        if std::mem::replace(&mut self.stopped, true) {
+            return Ok(());
+        }
+        self.run_stage(Shutdown(()))
+    }
+
+    /// Runs the systems of the event layer by layer, with a sync point after each layer,
+    /// until the event is cancelled. Returns true if the errors or an immediate exit should stop the step
+    fn dispatch(&mut self, event: SharedEvent, errors: &mut Vec<SystemError>) -> bool {
+        let cancel_notifier = self.scheduler.cancel_notifier(&*event);
+        let dispatch = Arc::new(Dispatch::new(event));
+
+        for layer in self.dispatch_plan(&dispatch.event) {
+            if self.exit == Some(ExitMode::Immediate) {
+                return true;
+            }
+            let systems = self.runnable(layer);
+            self.execute(systems, &dispatch);
+            if self.sync(errors) {
+                return true;
+            }
+
+            if dispatch.is_cancelled() {
+                if let Some(notifier) = cancel_notifier {
+                    let cancelled = notifier(dispatch.event.clone());
+                    self.with_event_queue(|event_queue| event_queue.push_boxed(cancelled));
+                }
+                break;
+            }
+        }
+        false
+    }
+
+    /// Runs the systems of a stage event regardless of the errors, exit requests and cancellation
+    fn run_stage(&mut self, stage: impl AnyHash + Send + Sync) -> Result<(), Vec<SystemError>> {
+        let mut errors = Vec::new();
+        let dispatch = Arc::new(Dispatch::new(Arc::new(stage)));
+
+        for layer in self.dispatch_plan(&dispatch.event) {
+            let systems = self.runnable(layer);
+            self.execute(systems, &dispatch);
+            self.sync(&mut errors);
+        }
+
+        into_result(errors)
+    }
+
+    /// Layers of systems to run one after the other for this event, each shuffled if needed
+    fn dispatch_plan(&mut self, event: &SharedEvent) -> Vec<Vec<Subscriber>> {
+        let mut layers = self.scheduler.dispatch_plan(&**event);
+        if let Some(rng) = &mut self.dispatch_rng {
+            layers.iter_mut().for_each(|layer| rng.shuffle(layer));
+        }
+        layers
+    }
+
+    /// The systems of the layer whose run condition is true, all the conditions are checked
+    /// before any system of the layer is sent so they never wait for a running system
+    fn runnable(&self, layer: Vec<Subscriber>) -> Vec<Subscriber> {
+        let globals = self.globals_cell.borrow();
+        let access = GlobalAccess::new(&globals);
+        layer
+            .into_iter()
+            .filter(|subscriber| {
+                let condition = subscriber.condition.as_ref();
+                condition.is_none_or(|condition| condition.check(access))
+            })
+            .collect()
+    }
+
+    /// Sends the systems to the thread pool, then runs the main thread ones meanwhile.
+    /// Without workers the systems run one after the other in the order of the layer
+    fn execute(&self, systems: Vec<Subscriber>, dispatch: &Arc<Dispatch>) {
+        let mut main_thread = Vec::new();
+        for subscriber in systems {
+            if subscriber.main_thread && !self.thread_pool.is_inline() {
+                main_thread.push(subscriber);
+            } else {
+                self.execute_one(subscriber, dispatch);
+            }
+        }
+        for subscriber in main_thread {
+            self.execute_one(subscriber, dispatch);
+        }
+    }
+
+    fn execute_one(&self, subscriber: Subscriber, dispatch: &Arc<Dispatch>) {
+        if subscriber.main_thread {
+            self.thread_pool.execute_here(
+                subscriber.id,
+                subscriber.system,
+                dispatch.clone(),
+                &self.locals,
+            );
+        } else {
+            self.thread_pool
+                .execute(subscriber.id, subscriber.system, dispatch.clone());
+        }
+    }
+
+    /// Steps until the event queue is empty or an exit is requested
+    pub fn run_until_idle(&mut self) -> Result<ExitReason, Vec<SystemError>> {
+        self.run_until(|runtime| (!runtime.has_pending_events()).then_some(ExitReason::Idle))
+    }
+
+    /// Keeps waiting for new events until an exit is requested, e.g. for servers
+    pub fn run_until_exit(&mut self) -> Result<ExitReason, Vec<SystemError>> {
+        self.run_until(|runtime| {
+            runtime.wait_for_events();
+            None
+        })
+    }
+
+    /// Same as `run_until_exit` but gives up after the timeout, e.g. for tests.
+    /// The deadline is checked between steps, so events that keep pushing events can't hold it off
+    pub fn run_until_exit_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<ExitReason, Vec<SystemError>> {
+        let deadline = Instant::now() + timeout;
+        self.run_until(|runtime| {
+            let now = Instant::now();
+            if now >= deadline {
+                return Some(ExitReason::TimedOut);
+            }
+            (!runtime.wait_for_events_timeout(deadline - now)).then_some(ExitReason::TimedOut)
+        })
+    }
+
+    /// Steps until `wait` returns a reason to stop or an exit is requested.
+    /// With `ErrorPolicy::Continue` the errors of every step are returned at the end
+    fn run_until(
+        &mut self,
+        mut wait: impl FnMut(&Self) -> Option<ExitReason>,
+    ) -> Result<ExitReason, Vec<SystemError>> {
+        self.exit = None;
+        let mut errors = Vec::new();
+
+        if let Err(startup_errors) = self.startup() {
+            errors.extend(startup_errors);
+            if self.scheduler.error_policy == ErrorPolicy::Abort {
+                return Err(errors);
+            }
+        }
+
+        let reason = loop {
+            if let Some(mode) = self.exit {
+                break ExitReason::Exit(mode);
+            }
+            if let Some(reason) = wait(self) {
+                break reason;
+            }
+            if let Err(step_errors) = self.step() {
+                errors.extend(step_errors);
+                if self.scheduler.error_policy == ErrorPolicy::Abort {
+                    return Err(errors);
+                }
+            }
+        };
+
+        into_result(errors).map(|()| reason)
+    }
+
+    /// Runs the shutdown systems if needed, stops the thread pool and gives the globals back.
+    /// Call `shutdown` before to get the errors of the shutdown systems
+    pub fn into_globals(mut self) -> Globals {
+        let _ = self.shutdown();
+        let SchedulerRuntime {
+            globals_cell,
+            thread_pool,
+            ..
+        } = self;
+        thread_pool.shutdown();
+        Arc::into_inner(globals_cell).unwrap().into_inner()
+    }
+
+    pub(crate) fn chaos_seed(&self) -> Option<u64> {
+        self.scheduler.chaos_seed
+    }
+
+    fn request_exit(&mut self, mode: ExitMode) {
+        self.exit = self.exit.max(Some(mode));
+    }
+
+    /// Waits for the running systems, handles their errors then applies the globals and scheduler commands.
+    /// Returns true if the errors should abort the run
+    fn sync(&mut self, errors: &mut Vec<SystemError>) -> bool {
+        self.thread_pool.wait_until_idle();
+
+        let new_errors = self.thread_pool.take_errors();
+        let abort = match self.scheduler.error_policy {
+            ErrorPolicy::Abort => {
+                errors.extend(new_errors);
+                !errors.is_empty()
+            }
+            ErrorPolicy::Continue => {
+                errors.extend(new_errors);
+                false
+            }
+            ErrorPolicy::Emit => {
+                // Emitting the errors of the error handlers could loop forever
+                let (handler_errors, new_errors): (Vec<_>, Vec<_>) = new_errors
+                    .into_iter()
+                    .partition(|error| (&*error.event as &dyn Any).is::<SystemErrored>());
+                errors.extend(handler_errors);
+                self.with_event_queue(|event_queue| {
+                    for error in new_errors {
+                        event_queue.push(SystemErrored(Arc::new(error)));
+                    }
+                });
+                false
+            }
+        };
+
+        let scheduler_commands = {
+            let mut globals = self.globals_cell.borrow_mut();
+            globals.update_command_queue();
+            self.scheduler.run_sync_hooks(&globals);
+            let commands = globals.remove(Scheduler::COMMANDS);
+            globals.insert(Singleton(SchedulerCommandQueue::new_empty()));
+            commands
+        };
+        for command in scheduler_commands
+            .into_iter()
+            .flat_map(|queue| queue.commands)
+        {
+            match command {
+                SchedulerCommand::Exit(mode) => self.request_exit(mode),
+                SchedulerCommand::Remove(id) => {
+                    self.scheduler.remove(id);
+                }
+                SchedulerCommand::Disable(id) => {
+                    self.scheduler.disable(id);
+                }
+                SchedulerCommand::Enable(id) => {
+                    self.scheduler.enable(id);
+                }
+                SchedulerCommand::Register(register) => register(&mut self.scheduler),
+            }
+        }
+
+        abort
+    }
+
+    fn with_event_queue<R>(&self, f: impl FnOnce(&mut EventQueue) -> R) -> R {
+        let globals = self.globals_cell.borrow();
+        let mut event_queue = globals
+            .get_mut(Singleton::<EventQueue>::key())
+            .expect("Could not retrieve event queue global!");
+        f(&mut event_queue)
+    }
+}
+
+fn into_result(errors: Vec<SystemError>) -> Result<(), Vec<SystemError>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
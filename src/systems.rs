@@ -1,132 +1,891 @@
 use std::{
-    any::Any,
+    any::{type_name, Any, TypeId},
+    cell::RefMut,
     collections::HashMap,
     fmt::Debug,
-    ops::{Deref, DerefMut},
-    sync::Arc,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 use any_key::AnyHash;
-use atomic_refcell::{AtomicRef, AtomicRefCell};
+use atomic_refcell::{AtomicRefCell, AtomicRefMut};
 
 use crate::{
-    events::EventQueue,
-    globals::{
-        GlobalMut, GlobalRef, Globals, GlobalsCommandQueue, IntoGlobalKey, IntoSingletonKey,
-        Singleton, SingletonKey,
-    },
-    threadpool::ThreadPool,
+    chaos::Chaos,
+    commands::SchedulerCommandQueue,
+    conditions::Condition,
+    errors::{ErrorPolicy, RunError, SystemError},
+    events::{BoxedEvent, Cancelled, Dispatch, EventSender, Events, Inbox, Shutdown, Startup},
+    globals::{Globals, IntoSingletonKey, Singleton, SingletonKey},
+    locals::{LocalGlobals, LocalsRef},
+    ordering::{self, Constraints},
+    runtime::{ExitReason, RunOutput, SchedulerRuntime},
+    threadpool::ThreadPoolConfig,
 };
 
 /// The `Scheduler` allows for systems declaration, scheduling and execution
 /// Systems can be scheduled to run when the scheduler receives a certain event
 /// Events can be anything Hashable and Send + Sync, see: https://docs.rs/any_key/latest/any_key/
+/// Systems either subscribe to an exact event value (`on`) or to every event of a type (`on_type`)
+/// The systems of an event run in parallel unless ordered with `SystemConfig::before/after`
+/// or registered in the `before`/`after` phases of the event
 pub struct Scheduler {
-    systems: HashMap<Box<dyn AnyHash>, Vec<Arc<System>>>, // Event typeid to system
+    registrations: HashMap<SystemId, Registration>,
+    systems: HashMap<SharedEvent, Vec<SystemId>>, // Event value to systems, keyed by the event of one of them
+    typed_systems: HashMap<TypeId, Vec<SystemId>>, // Event typeid to systems
+    pub(crate) error_policy: ErrorPolicy,
+    pub(crate) dispatch_order: DispatchOrder,
+    pub(crate) chaos_seed: Option<u64>,
+    pub(crate) pool_config: ThreadPoolConfig,
+    pub(crate) inbox: Arc<Inbox>,
+    ids: SystemIds,
+    sync_hooks: Vec<(usize, Box<SyncHook>)>,
+    step_hooks: Vec<(usize, Box<SyncHook>)>,
+    hooks_added: usize,
+}
+
+/// Order in which the systems bound to the same event are handed to the thread pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DispatchOrder {
+    /// Systems subscribed to the event value first, then the ones subscribed to its type,
+    /// each in registration order
+    #[default]
+    Registration,
+    /// The registration order shuffled for every event, the same seed always gives the same orders
+    Shuffled { seed: u64 },
 }
 
+/// Identifies a registered system, get it with `SystemConfig::id`
+/// Ids are unique across schedulers and never reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SystemId(u64);
+
+/// Hands out the system ids. Plugins loaded from shared libraries link their own copy of nano,
+/// so the counter is referenced by the schedulers and command queues created by the host
+/// instead of being read by whichever copy registers the system
+#[derive(Clone, Copy)]
+pub(crate) struct SystemIds(&'static AtomicU64);
+
+impl SystemIds {
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(&NEXT)
+    }
+
+    pub fn next(&self) -> SystemId {
+        SystemId(self.0.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+struct Registration {
+    system: Arc<System>,
+    event: Option<SharedEvent>, // `None` for the systems subscribed to an event type
+    event_type: TypeId,
+    cancelled: CancelNotifier,
+    phase: Phase,
+    constraints: Constraints,
+    condition: Option<Condition>,
+    enabled: bool,
+    main_thread: bool,
+}
+
+/// The phases of an event run one after the other, each waits for the previous one
+/// and sees the commands it queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Before,
+    Main,
+    After,
+}
+
+/// Runs at every sync point or at the end of every step,
+/// see `Scheduler::fold_at_sync` and `Scheduler::update_events`
+type SyncHook = dyn Fn(&Globals) + Send + Sync;
+
 pub(crate) type GlobalsCell = Arc<AtomicRefCell<Globals>>;
+/// An event being dispatched, shared by every system it triggered
+/// so that it lives until the last of them has finished
+pub(crate) type SharedEvent = Arc<dyn AnyHash + Send + Sync>;
+/// Builds the `Cancelled<E>` event of a cancelled event. Each registration keeps the one of its event type
+/// so that it lives as long as the code that registered it, e.g. a plugin loaded from a library
+pub(crate) type CancelNotifier = fn(SharedEvent) -> BoxedEvent;
+/// A system to dispatch and the condition to check before sending it to the thread pool
+pub(crate) struct Subscriber {
+    pub id: SystemId,
+    pub system: Arc<System>,
+    pub condition: Option<Condition>,
+    pub main_thread: bool,
+}
+
 impl Scheduler {
+    pub const COMMANDS: SingletonKey<SchedulerCommandQueue> = SchedulerCommandQueue::SINGLETON;
+
     pub fn new() -> Self {
-        Self {
-            systems: HashMap::new(),
+        Self::builder().build()
+    }
+
+    pub fn builder() -> SchedulerBuilder {
+        SchedulerBuilder::default()
+    }
+
+    /// Sends events to the scheduler from other threads, before or during the run
+    pub fn event_sender(&self) -> EventSender {
+        EventSender::new(self.inbox.clone())
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    pub fn on<E: AnyHash + Send + Sync, T>(
+        &mut self,
+        event: E,
+        sys: impl IntoSystem<T, E>,
+    ) -> SystemConfig<'_> {
+        self.on_phase(Phase::Main, event, sys)
+    }
+
+    /// Runs the system before every `on` and `on_type` system of the event, e.g. to validate it
+    pub fn before<E: AnyHash + Send + Sync, T>(
+        &mut self,
+        event: E,
+        sys: impl IntoSystem<T, E>,
+    ) -> SystemConfig<'_> {
+        self.on_phase(Phase::Before, event, sys)
+    }
+
+    /// Runs the system after every `on` and `on_type` system of the event has finished
+    /// and their commands were applied, e.g. to clamp values they modified
+    pub fn after<E: AnyHash + Send + Sync, T>(
+        &mut self,
+        event: E,
+        sys: impl IntoSystem<T, E>,
+    ) -> SystemConfig<'_> {
+        self.on_phase(Phase::After, event, sys)
+    }
+
+    fn on_phase<E: AnyHash + Send + Sync, T>(
+        &mut self,
+        phase: Phase,
+        event: E,
+        sys: impl IntoSystem<T, E>,
+    ) -> SystemConfig<'_> {
+        let id = self.ids.next();
+        self.insert_system(id, phase, event, sys.into_system());
+        SystemConfig {
+            scheduler: self,
+            id,
+        }
+    }
+
+    /// Runs the system on every event of type `E`, whatever its value
+    pub fn on_type<E: AnyHash + Send + Sync, T>(
+        &mut self,
+        sys: impl IntoSystem<T, E>,
+    ) -> SystemConfig<'_> {
+        let id = self.ids.next();
+        self.insert_typed_system::<E>(id, sys.into_system());
+        SystemConfig {
+            scheduler: self,
+            id,
         }
     }
 
-    pub fn on<T>(&mut self, event: impl AnyHash + Send + Sync, sys: impl IntoSystem<T>) {
-        self.systems
-            .entry(Box::new(event))
+    /// Runs the system once before the first event, e.g. to open files or sockets
+    pub fn on_startup<T>(&mut self, sys: impl IntoSystem<T, Startup>) -> SystemConfig<'_> {
+        self.on(Startup(()), sys)
+    }
+
+    /// Runs the system once the run ends, after the queue is empty or an exit was requested,
+    /// e.g. to flush files or sockets
+    pub fn on_shutdown<T>(&mut self, sys: impl IntoSystem<T, Shutdown>) -> SystemConfig<'_> {
+        self.on(Shutdown(()), sys)
+    }
+
+    /// Same as `on(event, sys).on_main_thread()`
+    pub fn on_main_thread<E: AnyHash + Send + Sync, T>(
+        &mut self,
+        event: E,
+        sys: impl IntoSystem<T, E>,
+    ) -> SystemConfig<'_> {
+        self.on(event, sys).on_main_thread()
+    }
+
+    pub(crate) fn insert_system<E: AnyHash + Send + Sync>(
+        &mut self,
+        id: SystemId,
+        phase: Phase,
+        event: E,
+        system: System,
+    ) {
+        let event: SharedEvent = Arc::new(event);
+        self.register::<E>(id, phase, Some(event.clone()), system);
+        self.systems.entry(event).or_default().push(id);
+    }
+
+    pub(crate) fn insert_typed_system<E: AnyHash + Send + Sync>(
+        &mut self,
+        id: SystemId,
+        system: System,
+    ) {
+        self.register::<E>(id, Phase::Main, None, system);
+        self.typed_systems
+            .entry(TypeId::of::<E>())
             .or_default()
-            .push(Arc::new(sys.into_system()));
-    }
-
-    pub fn run<T: AnyHash + Send + Sync>(self, start_event: T, globals: Globals) -> Globals {
-        let globals_cell: GlobalsCell = Arc::new(AtomicRefCell::new(globals));
-        {
-            let thread_pool = ThreadPool::new(&globals_cell);
-            let mut globals = globals_cell.borrow_mut();
-
-            let mut event_queue = EventQueue::new();
-            event_queue.push(start_event);
-            globals.insert(Singleton(event_queue));
-
-            drop(globals); // release mutable borrow
-
-            while let Some(events) = {
-                let globals = globals_cell.borrow(); // Not in scope when the loop runs
-                let mut event_queue = globals
-                    .get_mut(Singleton::<EventQueue>::key())
-                    .expect("Could not retrieve event queue global!");
-
-                Some(event_queue.drain())
-                    .filter(|events| events.len() > 0 || !thread_pool.finished_executing())
-            } {
-                events
-                    .into_iter()
-                    .filter_map(|event| self.systems.get(&(event as Box<dyn AnyHash>)))
-                    .for_each(|eventsyss| {
-                        while !thread_pool.finished_executing() {}
-
-                        globals_cell.borrow_mut().update_command_queue();
-
-                        for system in eventsyss {
-                            thread_pool.execute(system.clone());
-                        }
-                    });
+            .push(id);
+    }
+
+    fn register<E: AnyHash + Send + Sync>(
+        &mut self,
+        id: SystemId,
+        phase: Phase,
+        event: Option<SharedEvent>,
+        system: System,
+    ) {
+        self.registrations.insert(
+            id,
+            Registration {
+                system: Arc::new(system),
+                event,
+                event_type: TypeId::of::<E>(),
+                cancelled: Cancelled::<E>::boxed,
+                phase,
+                constraints: Constraints::default(),
+                condition: None,
+                enabled: true,
+                main_thread: false,
+            },
+        );
+    }
+
+    /// Unregisters the system, returns false if it was already removed.
+    /// If the scheduler is running, the system still runs for the event being dispatched
+    pub fn remove(&mut self, id: SystemId) -> bool {
+        let Some(registration) = self.registrations.remove(&id) else {
+            return false;
+        };
+        if let Some(event) = registration.event {
+            let (_, mut ids) = self.systems.remove_entry(&event).unwrap();
+            ids.retain(|other| *other != id);
+            // The key may be the event of the removed system, which was maybe created by a plugin
+            // about to be unloaded, so it is replaced by the event of a remaining system
+            if let Some(first) = ids.first() {
+                let event = self.registrations[first].event.clone().unwrap();
+                self.systems.insert(event, ids);
+            }
+        }
+        self.typed_systems.retain(|_, ids| {
+            ids.retain(|other| *other != id);
+            !ids.is_empty()
+        });
+        true
+    }
+
+    /// Skips the system until it is enabled again, its ordering constraints are ignored meanwhile.
+    /// Returns false if the system was removed
+    pub fn disable(&mut self, id: SystemId) -> bool {
+        self.set_enabled(id, false)
+    }
+
+    /// Returns false if the system was removed
+    pub fn enable(&mut self, id: SystemId) -> bool {
+        self.set_enabled(id, true)
+    }
+
+    pub fn is_enabled(&self, id: SystemId) -> bool {
+        self.registrations
+            .get(&id)
+            .is_some_and(|registration| registration.enabled)
+    }
+
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) fn system_ids(&self) -> Vec<SystemId> {
+        self.registrations.keys().copied().collect()
+    }
+
+    fn set_enabled(&mut self, id: SystemId, enabled: bool) -> bool {
+        self.registrations
+            .get_mut(&id)
+            .map(|registration| registration.enabled = enabled)
+            .is_some()
+    }
+
+    /// Builds the `Cancelled<E>` event if this event gets cancelled, `None` if no system handles it.
+    /// Taken when the dispatch starts, the systems may be removed before the event is cancelled
+    pub(crate) fn cancel_notifier(
+        &self,
+        event: &(dyn AnyHash + Send + Sync),
+    ) -> Option<CancelNotifier> {
+        let (_, registration) = self.subscribed(event).next()?;
+        Some(registration.cancelled)
+    }
+
+    /// The systems subscribed to the event value, then the ones subscribed to its type
+    fn subscribed<'a>(
+        &'a self,
+        event: &(dyn AnyHash + Send + Sync),
+    ) -> impl Iterator<Item = (SystemId, &'a Registration)> + 'a {
+        let by_value = self.systems.get(event).into_iter().flatten();
+        let by_type = self
+            .typed_systems
+            .get(&(event as &dyn Any).type_id())
+            .into_iter()
+            .flatten();
+        by_value
+            .chain(by_type)
+            .map(|id| (*id, &self.registrations[id]))
+    }
+
+    /// The systems of the event split in layers that have to run one after the other, phase by phase.
+    /// Inside a layer, the systems subscribed to the event value come first, then the ones subscribed to its type
+    pub(crate) fn dispatch_plan(
+        &self,
+        event: &(dyn AnyHash + Send + Sync),
+    ) -> Vec<Vec<Subscriber>> {
+        let registrations: Vec<(SystemId, &Registration)> = self
+            .subscribed(event)
+            .filter(|(_, registration)| registration.enabled)
+            .collect();
+
+        [Phase::Before, Phase::Main, Phase::After]
+            .into_iter()
+            .flat_map(|phase| {
+                let systems = registrations
+                    .iter()
+                    .filter(|(_, registration)| registration.phase == phase)
+                    .map(|&(id, registration)| {
+                        let subscriber = Subscriber {
+                            id,
+                            system: registration.system.clone(),
+                            condition: registration.condition.clone(),
+                            main_thread: registration.main_thread,
+                        };
+                        (subscriber, &registration.constraints)
+                    })
+                    .collect();
+                ordering::layers(systems).expect("Cycles are rejected at registration")
+            })
+            .collect()
+    }
+
+    /// Whether the ordering constraints of the systems of this event type and phase contain a cycle
+    fn has_cycle(&self, event_type: TypeId, phase: Phase) -> bool {
+        let typed = self.typed_systems.get(&event_type);
+        let constraints = |ids: &[SystemId]| {
+            ids.iter()
+                .chain(typed.into_iter().flatten())
+                .map(|id| &self.registrations[id])
+                .filter(|registration| registration.phase == phase)
+                .map(|registration| ((), &registration.constraints))
+                .collect()
+        };
+
+        let mut groups: Vec<Vec<((), &Constraints)>> = self
+            .systems
+            .iter()
+            .filter(|(event, _)| (&***event as &dyn Any).type_id() == event_type)
+            .map(|(_, ids)| constraints(ids))
+            .collect();
+        groups.push(constraints(&[]));
+
+        groups
+            .into_iter()
+            .any(|group| ordering::layers(group).is_none())
+    }
+
+    /// Moves the per-thread instances of `T` into the `U` singleton at every sync point,
+    /// e.g. to sum per-thread counters. The instances are reset to `T::default()`,
+    /// so each value is folded once. Does nothing while one of them is missing
+    pub fn fold_at_sync<T: Send + Default + 'static, U: 'static>(
+        &mut self,
+        fold: impl Fn(&mut U, T) + Send + Sync + 'static,
+    ) {
+        self.add_sync_hook(Box::new(move |globals| {
+            let (Some(per_thread), Some(mut target)) = (
+                globals.per_thread_instances::<T>(),
+                globals.get_mut(Singleton::<U>::key()),
+            ) else {
+                return;
+            };
+            per_thread.for_each(|instance| fold(&mut target, std::mem::take(instance)));
+        }));
+    }
+
+    /// Swaps the buffers of the `Events<T>` singleton at the end of every step,
+    /// so that its events are dropped at the end of the step after the one they were sent in.
+    /// Doesn't insert the singleton and does nothing while it is missing, see `App::add_events`
+    pub fn update_events<T: Send + Sync + 'static>(&mut self) {
+        let id = self.next_hook_id();
+        self.step_hooks.push((
+            id,
+            Box::new(|globals| {
+                if let Some(mut events) = globals.get_mut(Events::<T>::SINGLETON) {
+                    events.update();
+                }
+            }),
+        ));
+    }
+
+    fn add_sync_hook(&mut self, hook: Box<SyncHook>) {
+        let id = self.next_hook_id();
+        self.sync_hooks.push((id, hook));
+    }
+
+    fn next_hook_id(&mut self) -> usize {
+        self.hooks_added += 1;
+        self.hooks_added - 1
+    }
+
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) fn hook_ids(&self) -> Vec<usize> {
+        self.sync_hooks
+            .iter()
+            .chain(&self.step_hooks)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    #[cfg(feature = "dynamic-plugins")]
+    pub(crate) fn remove_hook(&mut self, id: usize) {
+        self.sync_hooks.retain(|(other, _)| *other != id);
+        self.step_hooks.retain(|(other, _)| *other != id);
+    }
+
+    /// Runs the hooks registered with `fold_at_sync`, no system can be running
+    pub(crate) fn run_sync_hooks(&self, globals: &Globals) {
+        for (_, hook) in &self.sync_hooks {
+            hook(globals);
+        }
+    }
+
+    /// Runs the hooks registered with `update_events`, no system can be running
+    pub(crate) fn run_step_hooks(&self, globals: &Globals) {
+        for (_, hook) in &self.step_hooks {
+            hook(globals);
+        }
+    }
+
+    /// Starts the thread pool, the returned runtime can then be driven step by step
+    pub fn into_runtime(self, globals: Globals) -> SchedulerRuntime {
+        SchedulerRuntime::new(self, globals)
+    }
+
+    /// Runs the systems from the start event until the event queue is empty or an exit is requested
+    pub fn run<T: AnyHash + Send + Sync>(
+        self,
+        start_event: T,
+        globals: Globals,
+    ) -> Result<RunOutput, RunError> {
+        self.run_with_locals(start_event, globals, LocalGlobals::new())
+    }
+
+    /// Same as `run` with the non-`Send` globals of the main thread systems,
+    /// they are handed back along with the globals
+    pub fn run_with_locals<T: AnyHash + Send + Sync>(
+        self,
+        start_event: T,
+        globals: Globals,
+        locals: LocalGlobals,
+    ) -> Result<RunOutput, RunError> {
+        self.run_with(
+            start_event,
+            globals,
+            locals,
+            SchedulerRuntime::run_until_idle,
+        )
+    }
+
+    /// Runs the systems from the start event and keeps waiting for events,
+    /// e.g. from an `EventSender`, until an exit is requested
+    pub fn run_until_exit<T: AnyHash + Send + Sync>(
+        self,
+        start_event: T,
+        globals: Globals,
+    ) -> Result<RunOutput, RunError> {
+        self.run_with(
+            start_event,
+            globals,
+            LocalGlobals::new(),
+            SchedulerRuntime::run_until_exit,
+        )
+    }
+
+    /// Same as `run_until_exit` but gives up after the timeout, e.g. for tests
+    pub fn run_until_exit_timeout<T: AnyHash + Send + Sync>(
+        self,
+        start_event: T,
+        globals: Globals,
+        timeout: Duration,
+    ) -> Result<RunOutput, RunError> {
+        self.run_with(start_event, globals, LocalGlobals::new(), |runtime| {
+            runtime.run_until_exit_timeout(timeout)
+        })
+    }
+
+    fn run_with<T: AnyHash + Send + Sync>(
+        self,
+        start_event: T,
+        globals: Globals,
+        locals: LocalGlobals,
+        run: impl FnOnce(&mut SchedulerRuntime) -> Result<ExitReason, Vec<SystemError>>,
+    ) -> Result<RunOutput, RunError> {
+        let mut runtime = self.into_runtime(globals);
+        *runtime.locals_mut() = locals;
+        runtime.push_event(start_event);
+        let mut result = run(&mut runtime);
+        if let Err(shutdown_errors) = runtime.shutdown() {
+            result = match result {
+                Ok(_) => Err(shutdown_errors),
+                Err(mut errors) => {
+                    errors.extend(shutdown_errors);
+                    Err(errors)
+                }
+            };
+        }
+        let chaos_seed = runtime.chaos_seed();
+        let locals = std::mem::take(runtime.locals_mut());
+        let globals = runtime.into_globals();
+
+        match result {
+            Ok(exit) => Ok(RunOutput {
+                globals,
+                locals,
+                exit,
+            }),
+            Err(errors) => {
+                if let Some(seed) = chaos_seed {
+                    eprintln!("Chaos run failed, reproduce it with seed {seed}");
+                }
+                Err(RunError {
+                    globals: Box::new(globals),
+                    locals,
+                    errors,
+                })
             }
+        }
+    }
+}
+
+/// Returned when registering a system to label it, order it relative
+/// to the other systems of the same event and phase, or give it a run condition
+pub struct SystemConfig<'a> {
+    scheduler: &'a mut Scheduler,
+    id: SystemId,
+}
+
+impl SystemConfig<'_> {
+    /// Used to remove, disable or enable the system later on
+    pub fn id(&self) -> SystemId {
+        self.id
+    }
+
+    /// A system can have several labels and a label can be shared by several systems
+    pub fn label(self, label: &'static str) -> Self {
+        self.constrain(label, |constraints| constraints.labels.push(label))
+    }
+
+    /// Runs the system before every system of the same event and phase labeled `label`
+    /// Panics if this creates a cycle
+    pub fn before(self, label: &'static str) -> Self {
+        self.constrain(label, |constraints| constraints.before.push(label))
+    }
+
+    /// Runs the system after every system of the same event and phase labeled `label`
+    /// Panics if this creates a cycle
+    pub fn after(self, label: &'static str) -> Self {
+        self.constrain(label, |constraints| constraints.after.push(label))
+    }
+
+    /// Runs the system on the thread running the scheduler instead of a worker,
+    /// while the other systems of its layer run on the workers.
+    /// It can access the non-`Send` globals through `GlobalAccess::locals`
+    pub fn on_main_thread(self) -> Self {
+        let registration = self.scheduler.registrations.get_mut(&self.id).unwrap();
+        registration.main_thread = true;
+        self
+    }
+
+    /// Only runs the system if the condition is true when the system is dispatched.
+    /// Calling it again combines the conditions with `Condition::and`
+    pub fn run_if(self, condition: impl Into<Condition>) -> Self {
+        let registration = self.scheduler.registrations.get_mut(&self.id).unwrap();
+        let condition = match registration.condition.take() {
+            Some(previous) => previous.and(condition),
+            None => condition.into(),
+        };
+        registration.condition = Some(condition);
+        self
+    }
+
+    /// Panics if the constraint creates a cycle, the scheduler is left as before the call
+    fn constrain(self, label: &str, f: impl FnOnce(&mut Constraints)) -> Self {
+        let registration = self.scheduler.registrations.get_mut(&self.id).unwrap();
+        let previous = registration.constraints.clone();
+        f(&mut registration.constraints);
+        let (event_type, phase) = (registration.event_type, registration.phase);
+        if self.scheduler.has_cycle(event_type, phase) {
+            let registration = self.scheduler.registrations.get_mut(&self.id).unwrap();
+            registration.constraints = previous;
+            panic!("Ordering systems with label `{label}` creates a cycle!");
+        }
+        self
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Configures the thread pool and the error policy of a `Scheduler`
+#[derive(Default)]
+pub struct SchedulerBuilder {
+    error_policy: ErrorPolicy,
+    dispatch_order: DispatchOrder,
+    chaos_seed: Option<u64>,
+    chaos_max_delay: Option<Duration>,
+    pool_config: ThreadPoolConfig,
+}
+
+impl SchedulerBuilder {
+    /// Number of worker threads, defaults to the available parallelism
+    /// 0 is the same as `inline`
+    pub fn threads(mut self, count: usize) -> Self {
+        self.pool_config.workers = Some(count);
+        self
+    }
+
+    /// Workers are named `{name}-{index}`, defaults to `nano-worker`
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.pool_config.thread_name = name.into();
+        self
+    }
+
+    /// Stack size of the worker threads in bytes
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.pool_config.stack_size = Some(size);
+        self
+    }
+
+    /// Spawns no thread, the systems run one at a time on the thread calling `Scheduler::run`
+    pub fn inline(self) -> Self {
+        self.threads(0)
+    }
+
+    pub fn dispatch_order(mut self, order: DispatchOrder) -> Self {
+        self.dispatch_order = order;
+        self
+    }
+
+    /// Runs the systems inline in the given order, so that the same start event and globals
+    /// always give the same results, e.g. for tests and replays
+    pub fn deterministic(self, order: DispatchOrder) -> Self {
+        self.inline().dispatch_order(order)
+    }
 
-            thread_pool.shutdown();
+    /// Shakes out ordering bugs: shuffles the systems of every event and the drained events,
+    /// and sleeps up to 1ms before every `access!`, all derived from the seed.
+    /// The seed is printed when the run fails
+    pub fn chaos(mut self, seed: u64) -> Self {
+        self.chaos_seed = Some(seed);
+        self.dispatch_order(DispatchOrder::Shuffled { seed })
+    }
+
+    /// Longest delay injected before `access!` in chaos mode
+    pub fn chaos_max_delay(mut self, max_delay: Duration) -> Self {
+        self.chaos_max_delay = Some(max_delay);
+        self
+    }
+
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    pub fn build(mut self) -> Scheduler {
+        let max_delay = self.chaos_max_delay.unwrap_or(Duration::from_millis(1));
+        self.pool_config.chaos = self.chaos_seed.map(|seed| (seed, max_delay));
+        Scheduler {
+            registrations: HashMap::new(),
+            systems: HashMap::new(),
+            typed_systems: HashMap::new(),
+            sync_hooks: Vec::new(),
+            step_hooks: Vec::new(),
+            hooks_added: 0,
+            error_policy: self.error_policy,
+            dispatch_order: self.dispatch_order,
+            chaos_seed: self.chaos_seed,
+            pool_config: self.pool_config,
+            inbox: Arc::default(),
+            ids: SystemIds::new(),
         }
-        Arc::into_inner(globals_cell).unwrap().into_inner()
     }
 }
 
+type SystemFn =
+    dyn Fn(GlobalAccess, &dyn Any) -> Result<(), Box<dyn CustomSystemError>> + Send + Sync; // &dyn Any is the event
+
 pub struct System {
-    wrapped_fn:
-        Arc<dyn Fn(AtomicRef<Globals>) -> Result<(), Box<dyn CustomSystemError>> + Send + Sync>, // Box<dyn Any> is the event
+    name: &'static str,
+    wrapped_fn: Arc<SystemFn>,
 }
 
 impl System {
-    pub fn run(&self, globals: AtomicRef<Globals>) -> Result<(), Box<dyn CustomSystemError>> {
-        (self.wrapped_fn)(globals)
+    /// The type name of the function the system was made from
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn run(
+        &self,
+        globals: GlobalAccess,
+        event: &dyn Any,
+    ) -> Result<(), Box<dyn CustomSystemError>> {
+        (self.wrapped_fn)(globals, event)
     }
 }
 
-pub trait CustomSystemError: Any + Debug {}
-impl<T: Any + Debug> CustomSystemError for T {}
+pub trait CustomSystemError: Any + Debug + Send + Sync {}
+impl<T: Any + Debug + Send + Sync> CustomSystemError for T {}
 
-pub trait IntoSystem<T> {
+/// Turns a function into a system registered for events of type `E`,
+/// a system taking the event as its second argument only accepts `&E`
+pub trait IntoSystem<T, E> {
     fn into_system(self) -> System;
 }
 
 /// Wraps a `&Globals` to avoid deadlocks.
 /// Use with the `access!` macro
+#[derive(Clone, Copy)]
 pub struct GlobalAccess<'a> {
     pub inner_may_deadlock: &'a Globals,
+    pub(crate) chaos: Option<&'a Chaos>,
+    pub(crate) dispatch: Option<&'a Dispatch>,
+    pub(crate) locals: Option<LocalsRef<'a>>,
+    pub(crate) thread_slot: usize, // Given by the thread pool, see `GlobalAccess::per_thread`
+    pub(crate) system: Option<SystemId>,
 }
 
-impl<T: CustomSystemError, F: Fn(GlobalAccess) -> Result<(), T> + 'static + Send + Sync>
-    IntoSystem<(T, ())> for F
+impl<'a> GlobalAccess<'a> {
+    pub fn new(globals: &'a Globals) -> Self {
+        Self {
+            inner_may_deadlock: globals,
+            chaos: None,
+            dispatch: None,
+            locals: None,
+            thread_slot: 0,
+            system: None,
+        }
+    }
+
+    /// Skips the systems of the event that haven't started yet, i.e. the ones of the next layers and phases,
+    /// and pushes a `Cancelled<E>` event once the running ones have finished.
+    /// The startup and shutdown stages can't be cancelled
+    pub fn cancel_event(&self) {
+        if let Some(dispatch) = self.dispatch {
+            dispatch.cancel();
+        }
+    }
+
+    pub fn is_event_cancelled(&self) -> bool {
+        self.dispatch.is_some_and(Dispatch::is_cancelled)
+    }
+
+    /// The system this access was given to, `None` outside of systems
+    pub fn system(&self) -> Option<SystemId> {
+        self.system
+    }
+
+    /// The instance of the per-thread global `T` of the thread running the system,
+    /// see `Globals::insert_per_thread`. Panics if the system already borrows it
+    pub fn per_thread<T: Send + 'static>(&self) -> Option<AtomicRefMut<'a, T>> {
+        let per_thread = self.inner_may_deadlock.per_thread_instances::<T>()?;
+        Some(per_thread.get(self.thread_slot))
+    }
+
+    /// The globals that stay on the thread running the scheduler,
+    /// only available to the systems registered with `SystemConfig::on_main_thread`.
+    /// `None` when called from another thread, e.g. one spawned by the system.
+    /// Panics if the system already borrows them
+    pub fn locals(&self) -> Option<RefMut<'a, LocalGlobals>> {
+        self.locals?.get()
+    }
+
+    /// Called by `access!` right before locking globals
+    #[doc(hidden)]
+    pub fn before_lock(&self) {
+        if let Some(chaos) = self.chaos {
+            chaos.delay();
+        }
+    }
+}
+
+impl<E, T: CustomSystemError, F: Fn(GlobalAccess) -> Result<(), T> + 'static + Send + Sync>
+    IntoSystem<(T, ()), E> for F
 {
     fn into_system(self) -> System {
         System {
-            wrapped_fn: Arc::new(move |g| {
-                self(GlobalAccess {
-                    inner_may_deadlock: g.deref(),
-                })
-                .map_err(|e| Box::new(e) as Box<dyn CustomSystemError>)
+            name: type_name::<F>(),
+            wrapped_fn: Arc::new(move |g, _| {
+                self(g).map_err(|e| Box::new(e) as Box<dyn CustomSystemError>)
+            }),
+        }
+    }
+}
+
+impl<E, F: Fn(GlobalAccess) + 'static + Send + Sync> IntoSystem<(), E> for F {
+    fn into_system(self) -> System {
+        System {
+            name: type_name::<F>(),
+            wrapped_fn: Arc::new(move |g, _| {
+                self(g);
+                Ok(())
             }),
         }
     }
 }
 
-impl<F: Fn(GlobalAccess) + 'static + Send + Sync> IntoSystem<()> for F {
+/// Marker for systems that also receive the event that triggered them,
+/// e.g. `fn on_damage(g: GlobalAccess, event: &DamageEvent)`
+pub struct WithEvent<E, R = ()>(PhantomData<fn(&E) -> R>);
+
+fn downcast_event<E: 'static>(event: &dyn Any) -> &E {
+    event
+        .downcast_ref()
+        .unwrap_or_else(|| panic!("System expected an event of type {} !", type_name::<E>()))
+}
+
+impl<
+        E: 'static,
+        T: CustomSystemError,
+        F: Fn(GlobalAccess, &E) -> Result<(), T> + 'static + Send + Sync,
+    > IntoSystem<WithEvent<E, (T, ())>, E> for F
+{
+    fn into_system(self) -> System {
+        System {
+            name: type_name::<F>(),
+            wrapped_fn: Arc::new(move |g, event| {
+                self(g, downcast_event(event))
+                    .map_err(|e| Box::new(e) as Box<dyn CustomSystemError>)
+            }),
+        }
+    }
+}
+
+impl<E: 'static, F: Fn(GlobalAccess, &E) + 'static + Send + Sync> IntoSystem<WithEvent<E>, E>
+    for F
+{
     fn into_system(self) -> System {
         System {
-            wrapped_fn: Arc::new(move |g| {
-                Ok(self(GlobalAccess {
-                    inner_may_deadlock: g.deref(),
-                }))
+            name: type_name::<F>(),
+            wrapped_fn: Arc::new(move |g, event| {
+                self(g, downcast_event(event));
+                Ok(())
             }),
         }
     }
@@ -1,98 +1,302 @@
 use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
     sync::{
-        atomic::{AtomicU32, Ordering},
-        mpsc, Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
     },
     thread,
+    time::Duration,
 };
 
-use crate::systems::{GlobalsCell, System};
+use crate::{
+    chaos::Chaos,
+    errors::{SystemError, SystemPanic},
+    events::Dispatch,
+    locals::{LocalGlobals, LocalsRef},
+    systems::{GlobalAccess, GlobalsCell, System, SystemId},
+};
+
+type Job = (SystemId, Arc<System>, Arc<Dispatch>);
+
+/// How the thread pool spawns its workers, see `SchedulerBuilder`
+#[derive(Clone)]
+pub(crate) struct ThreadPoolConfig {
+    /// `None` uses the available parallelism, `Some(0)` runs the systems inline
+    pub workers: Option<usize>,
+    pub thread_name: String,
+    pub stack_size: Option<usize>,
+    pub chaos: Option<(u64, Duration)>, // Seed and max delay before locking globals
+}
 
-type Sender = Arc<mpsc::Sender<Arc<System>>>;
-type Receiver = Arc<Mutex<mpsc::Receiver<Arc<System>>>>;
+impl Default for ThreadPoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: None,
+            thread_name: "nano-worker".to_string(),
+            stack_size: None,
+            chaos: None,
+        }
+    }
+}
 
+/// Every worker owns a deque of jobs, it pops from the front of its own deque
+/// and steals from the back of the others' once it runs out of work.
+/// Jobs are handed to the workers in a round robin fashion.
+/// Without workers, jobs run inline on the thread calling `execute`.
 pub struct ThreadPool {
     workers: Vec<Worker>,
-    sender: Sender,
-    receiver: Receiver,
-    running: Arc<AtomicU32>,
+    shared: Arc<Shared>,
+    next_worker: AtomicUsize,
+}
+
+struct Shared {
+    queues: Vec<Mutex<VecDeque<Job>>>,
+    queued: AtomicUsize,   // Jobs sitting in the queues
+    sleeping: AtomicUsize, // Workers waiting for jobs
+    sleep: Mutex<()>,
+    wake_up: Condvar,
+    shutdown: AtomicBool,
+    running: Running,
+    errors: Mutex<Vec<SystemError>>,
+    globals_cell: GlobalsCell,
+    chaos: Option<Chaos>,
+}
+
+/// Counts the systems sent to the pool that haven't finished yet
+/// and wakes up whoever is waiting for it to reach zero
+#[derive(Default)]
+struct Running {
+    count: AtomicU32,
+    lock: Mutex<()>,
+    idle: Condvar,
+}
+
+impl Running {
+    fn start(&self) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn finish(&self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _lock = self.lock.lock().unwrap();
+            self.idle.notify_all();
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.count.load(Ordering::SeqCst) == 0
+    }
+
+    fn wait_until_idle(&self) {
+        let lock = self.lock.lock().unwrap();
+        drop(self.idle.wait_while(lock, |_| !self.is_idle()).unwrap());
+    }
 }
 
 impl ThreadPool {
-    pub fn new(globals: &GlobalsCell) -> Self {
-        let (sender, receiver) = mpsc::channel();
-
-        let sender = Arc::new(sender);
-        let receiver = Arc::new(Mutex::new(receiver));
-
-        let running = Arc::new(AtomicU32::new(0));
-
-        let workers = (0..thread::available_parallelism()
-            .map(|nzu| nzu.into())
-            .unwrap_or(num_cpus::get()))
-            .map(|id| {
-                Worker::new(
-                    id,
-                    sender.clone(),
-                    receiver.clone(),
-                    running.clone(),
-                    globals.clone(),
-                )
+    pub fn new(globals: &GlobalsCell, config: &ThreadPoolConfig) -> Self {
+        let size = if cfg!(feature = "single-threaded") {
+            0
+        } else {
+            config.workers.unwrap_or_else(|| {
+                thread::available_parallelism()
+                    .map(|nzu| nzu.into())
+                    .unwrap_or(num_cpus::get())
             })
+        };
+
+        let shared = Arc::new(Shared {
+            queues: (0..size).map(|_| Mutex::default()).collect(),
+            queued: AtomicUsize::new(0),
+            sleeping: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            wake_up: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+            running: Running::default(),
+            errors: Mutex::default(),
+            globals_cell: globals.clone(),
+            chaos: config
+                .chaos
+                .map(|(seed, max_delay)| Chaos::new(seed, max_delay)),
+        });
+
+        let workers = (0..size)
+            .map(|id| Worker::new(id, shared.clone(), config))
             .collect();
 
         ThreadPool {
             workers,
-            sender,
-            receiver,
-            running,
+            shared,
+            next_worker: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn execute(&self, id: SystemId, system: Arc<System>, dispatch: Arc<Dispatch>) {
+        self.shared.running.start();
+        if self.workers.is_empty() {
+            self.shared.run_job((id, system, dispatch), 0, None);
+            return;
         }
+
+        let worker = self.next_worker.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        // Counted first, a worker may pop the job and decrement the count right after the push
+        self.shared.queued.fetch_add(1, Ordering::SeqCst);
+        self.shared.queues[worker]
+            .lock()
+            .unwrap()
+            .push_back((id, system, dispatch));
+
+        if self.shared.sleeping.load(Ordering::SeqCst) > 0 {
+            let _sleep = self.shared.sleep.lock().unwrap();
+            self.shared.wake_up.notify_one();
+        }
+    }
+
+    /// Runs the system on the calling thread with access to its local globals,
+    /// its errors are reported like the ones of the workers
+    pub fn execute_here(
+        &self,
+        id: SystemId,
+        system: Arc<System>,
+        dispatch: Arc<Dispatch>,
+        locals: &RefCell<LocalGlobals>,
+    ) {
+        self.shared.running.start();
+        let locals = LocalsRef::new(locals);
+        self.shared.run_job((id, system, dispatch), 0, Some(locals));
+    }
+
+    /// Without workers every system runs on the thread calling `execute`
+    pub fn is_inline(&self) -> bool {
+        self.workers.is_empty()
     }
 
-    pub fn execute(&self, system: Arc<System>) {
-        self.running.fetch_add(1, Ordering::SeqCst);
-        self.sender.send(system).unwrap()
+    /// Per-thread globals slots needed, one for the thread calling `execute` and one per worker
+    pub fn thread_slots(&self) -> usize {
+        self.workers.len() + 1
     }
 
-    pub fn finished_executing(&self) -> bool {
-        self.running.load(Ordering::SeqCst) == 0
+    /// Blocks until every system sent to the pool has finished
+    pub fn wait_until_idle(&self) {
+        self.shared.running.wait_until_idle()
     }
 
-    pub fn shutdown(mut self) {
-        let handles = self
-            .workers
+    /// Errors returned by the systems since the last call
+    pub fn take_errors(&self) -> Vec<SystemError> {
+        std::mem::take(&mut self.shared.errors.lock().unwrap())
+    }
+
+    /// Waits for the workers to finish their jobs and exit, same as dropping the pool
+    pub fn shutdown(self) {
+        drop(self)
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        {
+            let _sleep = self.shared.sleep.lock().unwrap();
+            self.shared.wake_up.notify_all();
+        }
+
+        // Workers catch the panics of the systems, a failed join can only come from the pool itself
+        // and panicking again while unwinding would abort
+        self.workers
             .iter_mut()
             .filter_map(|w| w.thread.take())
-            .collect::<Vec<_>>();
-        drop(self);
-        handles.into_iter().for_each(|t| t.join().unwrap());
+            .for_each(|t| {
+                let _ = t.join();
+            });
+    }
+}
+
+impl Shared {
+    /// Pops a job from the worker's own queue, or steals one from another worker
+    fn find_job(&self, id: usize) -> Option<Job> {
+        let own_job = self.queues[id].lock().unwrap().pop_front();
+        let job = own_job.or_else(|| {
+            (1..self.queues.len())
+                .map(|offset| (id + offset) % self.queues.len())
+                .find_map(|other| self.queues[other].lock().unwrap().pop_back())
+        })?;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        Some(job)
+    }
+
+    /// `thread_slot` is the per-thread globals slot of the thread running the job
+    fn run_job(
+        &self,
+        (id, system, dispatch): Job,
+        thread_slot: usize,
+        locals: Option<LocalsRef<'_>>,
+    ) {
+        // A panicking system is reported like an erroring one and the worker stays alive
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let globals = self.globals_cell.borrow();
+            let access = GlobalAccess {
+                inner_may_deadlock: &globals,
+                chaos: self.chaos.as_ref(),
+                dispatch: Some(&dispatch),
+                locals,
+                thread_slot,
+                system: Some(id),
+            };
+            system.run(access, &*dispatch.event)
+        }))
+        .unwrap_or_else(|payload| Err(Box::new(SystemPanic::from_payload(payload))));
+
+        if let Err(error) = result {
+            self.errors.lock().unwrap().push(SystemError {
+                system: id,
+                system_name: system.name(),
+                event: dispatch.event.clone(),
+                error,
+            });
+        }
+        self.running.finish();
     }
 }
 
 struct Worker {
-    id: usize,
     thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
-    fn new(
-        id: usize,
-        _sender: Sender,
-        receiver: Receiver,
-        running: Arc<AtomicU32>,
-        globals_cell: GlobalsCell,
-    ) -> Worker {
-        let thread = Some(thread::spawn(move || loop {
-            let Ok(system) = receiver.lock().unwrap().recv() else {
-                break;
-            };
+    fn new(id: usize, shared: Arc<Shared>, config: &ThreadPoolConfig) -> Worker {
+        let mut builder = thread::Builder::new().name(format!("{}-{id}", config.thread_name));
+        if let Some(stack_size) = config.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+
+        let thread = builder.spawn(move || {
+            loop {
+                if let Some(job) = shared.find_job(id) {
+                    shared.run_job(job, id + 1, None);
+                    continue;
+                }
+
+                // Give the dispatcher a chance to push more jobs before going to sleep
+                thread::yield_now();
+                if shared.queued.load(Ordering::SeqCst) > 0 {
+                    continue;
+                }
 
-            if let Err(e) = system.run(globals_cell.borrow()) {
-                panic!("System errored: {e:?}")
+                let sleep = shared.sleep.lock().unwrap();
+                if shared.shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                shared.sleeping.fetch_add(1, Ordering::SeqCst);
+                if shared.queued.load(Ordering::SeqCst) == 0 {
+                    drop(shared.wake_up.wait(sleep).unwrap());
+                }
+                shared.sleeping.fetch_sub(1, Ordering::SeqCst);
             }
-            running.fetch_sub(1, Ordering::SeqCst);
-        }));
+        });
+        let thread = Some(thread.expect("Could not spawn worker thread!"));
 
-        Worker { id, thread }
+        Worker { thread }
     }
 }
@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+use nano::app::{App, Plugin, PluginId};
+
+/// Records the order in which the plugins were built
+#[derive(Clone, Default)]
+struct Built(Arc<Mutex<Vec<&'static str>>>);
+
+impl Built {
+    fn push(&self, name: &'static str) {
+        self.0.lock().unwrap().push(name);
+    }
+
+    fn order(&self) -> Vec<&'static str> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+struct Physics(Built);
+struct Render(Built);
+/// Adds `Physics` from its `build`
+struct Game(Built);
+
+impl Plugin for Physics {
+    fn build(&self, _: &mut App) {
+        self.0.push("physics");
+    }
+
+    // `PluginId::of::<Physics>()` still has the type name, only the type must be compared
+    fn name(&self) -> &'static str {
+        "physics"
+    }
+}
+
+impl Plugin for Render {
+    fn build(&self, _: &mut App) {
+        self.0.push("render");
+    }
+
+    fn dependencies(&self) -> Vec<PluginId> {
+        vec![PluginId::of::<Physics>()]
+    }
+}
+
+impl Plugin for Game {
+    fn build(&self, app: &mut App) {
+        self.0.push("game");
+        app.add_plugin(Physics(self.0.clone()));
+    }
+}
+
+#[test]
+fn dependencies_are_built_first() {
+    let built = Built::default();
+    let mut app = App::new();
+    app.add_plugin(Render(built.clone()))
+        .add_plugin(Physics(built.clone()))
+        .build_plugins();
+    assert_eq!(built.order(), ["physics", "render"]);
+    assert!(app.has_plugin(PluginId::of::<Physics>()));
+}
+
+#[test]
+fn dependencies_can_be_added_by_another_plugin() {
+    let built = Built::default();
+    let mut app = App::new();
+    app.add_plugin(Render(built.clone()))
+        .add_plugin(Game(built.clone()))
+        .build_plugins();
+    assert_eq!(built.order(), ["game", "physics", "render"]);
+    assert!(app.has_plugin(PluginId::of::<Render>()));
+}
+
+#[test]
+#[should_panic(expected = "which wasn't added")]
+fn missing_dependencies_panic() {
+    let mut app = App::new();
+    app.add_plugin(Render(Built::default())).build_plugins();
+}
@@ -0,0 +1,239 @@
+use std::sync::{Arc, Mutex, OnceLock};
+
+use nano::{
+    access,
+    conditions::Condition,
+    events::{Cancelled, EventQueue},
+    globals::{Globals, IntoSingletonKey, Singleton},
+    systems::{GlobalAccess, Scheduler},
+};
+
+#[derive(PartialEq, Eq, Hash)]
+struct Start;
+
+struct Validated;
+struct Moved;
+
+#[test]
+fn phases_run_in_order_and_see_the_previous_commands() {
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+
+    let record = ran.clone();
+    scheduler.after(Start, move |g: GlobalAccess| {
+        access! { g |
+            &_moved: Moved::SINGLETON,
+        };
+        record.lock().unwrap().push("after");
+    });
+    for _ in 0..4 {
+        let record = ran.clone();
+        scheduler.on(Start, move |g: GlobalAccess| {
+            access! { g |
+                &_validated: Validated::SINGLETON,
+                &mut commands: Globals::COMMANDS,
+            };
+            commands.insert(Singleton(Moved));
+            record.lock().unwrap().push("main");
+        });
+    }
+    let record = ran.clone();
+    scheduler.before(Start, move |g: GlobalAccess| {
+        access! { g |
+            &mut commands: Globals::COMMANDS,
+        };
+        commands.insert(Singleton(Validated));
+        record.lock().unwrap().push("before");
+    });
+
+    scheduler.run(Start, Globals::new()).unwrap();
+    assert_eq!(
+        *ran.lock().unwrap(),
+        ["before", "main", "main", "main", "main", "after"]
+    );
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Move(i32);
+
+#[test]
+fn cancelled_events_skip_the_next_layers_and_are_notified() {
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    let moved = Arc::new(Mutex::new(Vec::new()));
+    let cancelled = Arc::new(Mutex::new(Vec::new()));
+
+    scheduler.on(Start, |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        [1, -1, 2]
+            .into_iter()
+            .for_each(|n| event_queue.push(Move(n)));
+    });
+    scheduler.before(Move(-1), |g: GlobalAccess| g.cancel_event());
+    let record = moved.clone();
+    scheduler.on_type::<Move, _>(move |_: GlobalAccess, event: &Move| {
+        record.lock().unwrap().push(event.0);
+    });
+    let record = cancelled.clone();
+    scheduler.on_type::<Cancelled<Move>, _>(move |_: GlobalAccess, event: &Cancelled<Move>| {
+        record.lock().unwrap().push(event.0 .0);
+    });
+
+    scheduler.run(Start, Globals::new()).unwrap();
+    let mut moved = moved.lock().unwrap().clone();
+    moved.sort();
+    assert_eq!(moved, [1, 2]);
+    assert_eq!(*cancelled.lock().unwrap(), [-1]);
+}
+
+#[test]
+fn systems_can_cancel_their_event_and_remove_themselves() {
+    let mut scheduler = Scheduler::builder().inline().build();
+    let guard = Arc::new(OnceLock::new());
+    let cancelled = Arc::new(Mutex::new(0));
+
+    let own_id = guard.clone();
+    let id = scheduler
+        .before(Start, move |g: GlobalAccess| {
+            g.cancel_event();
+            access! { g |
+                &mut commands: Scheduler::COMMANDS,
+            };
+            commands.remove(*own_id.get().unwrap());
+        })
+        .id();
+    guard.set(id).unwrap();
+    // The guard is the only system of the event, so nothing else keeps its event type registered
+    let record = cancelled.clone();
+    scheduler.on_type::<Cancelled<Start>, _>(move |_: GlobalAccess| {
+        *record.lock().unwrap() += 1;
+    });
+
+    scheduler.run(Start, Globals::new()).unwrap();
+    assert_eq!(*cancelled.lock().unwrap(), 1);
+}
+
+struct Paused;
+struct DebugOverlay;
+
+#[test]
+fn run_conditions_combine_with_and_or_not() {
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let paused = Condition::singleton_exists::<Paused>();
+    let debug = Condition::singleton_exists::<DebugOverlay>();
+
+    let conditions = [
+        ("paused", paused.clone()),
+        ("!paused", !paused.clone()),
+        ("paused and debug", paused.clone().and(debug.clone())),
+        ("paused or debug", paused.clone().or(debug.clone())),
+        ("!(paused and debug)", !paused.and(debug)),
+    ];
+    for (name, condition) in conditions {
+        let record = ran.clone();
+        scheduler
+            .on(Start, move |_: GlobalAccess| {
+                record.lock().unwrap().push(name);
+            })
+            .run_if(condition);
+    }
+    let record = ran.clone();
+    scheduler
+        .on(Start, move |_: GlobalAccess| {
+            record.lock().unwrap().push("closure");
+        })
+        .run_if(|g: GlobalAccess| g.inner_may_deadlock.get(Paused::SINGLETON).is_none());
+
+    let mut globals = Globals::new();
+    globals.insert(Singleton(Paused));
+    scheduler.run(Start, globals).unwrap();
+
+    let mut ran = ran.lock().unwrap().clone();
+    ran.sort();
+    assert_eq!(ran, ["!(paused and debug)", "paused", "paused or debug"]);
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct Step(u32);
+
+#[test]
+fn systems_can_be_disabled_and_enabled_during_a_run() {
+    let mut scheduler = Scheduler::builder().inline().build();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+
+    scheduler.on(Start, |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        (0..4).for_each(|n| event_queue.push(Step(n)));
+    });
+    let record = ran.clone();
+    let stepper = scheduler
+        .on_type::<Step, _>(move |_: GlobalAccess, step: &Step| {
+            record.lock().unwrap().push(step.0);
+        })
+        .id();
+    // Applied at the sync point ending the layer, the systems of the same layer still run
+    scheduler.on(Step(0), move |g: GlobalAccess| {
+        access! { g |
+            &mut commands: Scheduler::COMMANDS,
+        };
+        commands.disable(stepper);
+    });
+    scheduler.on(Step(2), move |g: GlobalAccess| {
+        access! { g |
+            &mut commands: Scheduler::COMMANDS,
+        };
+        commands.enable(stepper);
+    });
+
+    let disabled = scheduler
+        .on(Start, |_: GlobalAccess| -> () { panic!("Disabled") })
+        .id();
+    assert!(scheduler.disable(disabled));
+    assert!(!scheduler.is_enabled(disabled));
+    let removed = scheduler
+        .on(Start, |_: GlobalAccess| -> () { panic!("Removed") })
+        .id();
+    assert!(scheduler.remove(removed));
+    assert!(!scheduler.remove(removed));
+    assert!(!scheduler.enable(removed));
+
+    scheduler.run(Start, Globals::new()).unwrap();
+    assert_eq!(*ran.lock().unwrap(), [0, 3]);
+}
+
+#[test]
+fn systems_can_register_systems_during_a_run() {
+    let mut scheduler = Scheduler::builder().threads(2).build();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+
+    let record = ran.clone();
+    scheduler.on(Start, move |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+            &mut commands: Scheduler::COMMANDS,
+        };
+        let on_step = record.clone();
+        commands.on(Step(1), move |_: GlobalAccess| {
+            on_step.lock().unwrap().push("step");
+        });
+        let on_move = record.clone();
+        commands.on_type::<Move, _>(move |_: GlobalAccess, event: &Move| {
+            assert_eq!(event.0, 5);
+            on_move.lock().unwrap().push("move");
+        });
+        // The id can be used right away, the commands are applied in order
+        let disabled = commands.on(Step(1), |_: GlobalAccess| -> () { panic!("Disabled") });
+        commands.disable(disabled);
+        event_queue.push(Step(1));
+        event_queue.push(Move(5));
+    });
+
+    scheduler.run(Start, Globals::new()).unwrap();
+    let mut ran = ran.lock().unwrap().clone();
+    ran.sort();
+    assert_eq!(ran, ["move", "step"]);
+}
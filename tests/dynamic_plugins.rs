@@ -0,0 +1,118 @@
+use std::{
+    env::consts::{DLL_PREFIX, DLL_SUFFIX},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use nano::{
+    access,
+    app::{App, PluginId},
+    events::{Cancelled, EventQueue},
+    globals::{IntoSingletonKey, Singleton},
+    systems::GlobalAccess,
+};
+
+#[derive(PartialEq, Eq, Hash)]
+struct Start;
+
+struct HostValue(u32);
+
+/// Builds `fixtures/greeter_plugin` once, in its own target directory.
+/// Its copy of nano must be built with the same features as the host
+fn plugin_path() -> &'static Path {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fixtures");
+        let mut cargo = Command::new(env!("CARGO"));
+        cargo
+            .args(["build", "-p", "greeter_plugin", "--target-dir"])
+            .arg(&target_dir);
+        if cfg!(feature = "single-threaded") {
+            cargo.args(["--features", "nano/single-threaded"]);
+        }
+        let status = cargo.status().expect("Could not run cargo");
+        assert!(status.success(), "Could not build the plugin fixture");
+        target_dir
+            .join("debug")
+            .join(format!("{DLL_PREFIX}greeter_plugin{DLL_SUFFIX}"))
+    })
+}
+
+fn load_plugin(app: &mut App) -> PluginId {
+    // SAFETY: `plugin_path` builds the fixture against this nano, with the host's features
+    unsafe { app.load_plugin(plugin_path()) }.unwrap()
+}
+
+/// An app with a startup system pushing a `String` event, the plugin greets it.
+/// The greetings of the plugin end up in the `Vec<String>` singleton
+fn host_app(startups: Arc<AtomicUsize>) -> App {
+    let mut app = App::new();
+    app.globals.insert(Singleton(Vec::<String>::new()));
+    app.globals.insert(Singleton(HostValue(7)));
+    app.scheduler.on_startup(move |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        startups.fetch_add(1, Ordering::Relaxed);
+        event_queue.push("Johny".to_string());
+    });
+    app
+}
+
+#[test]
+fn plugins_can_be_loaded_again_after_unloading() {
+    let startups = Arc::new(AtomicUsize::new(0));
+    let mut app = host_app(startups.clone());
+
+    let plugin = load_plugin(&mut app);
+    assert!(app.has_plugin(plugin));
+    assert!(app.unload_plugin(plugin));
+    assert!(!app.has_plugin(plugin));
+    assert!(!app.unload_plugin(plugin));
+    let plugin = load_plugin(&mut app);
+    assert!(app.has_plugin(plugin));
+
+    let output = app.run(Start).unwrap();
+    let mut log = output
+        .globals
+        .get(Vec::<String>::SINGLETON)
+        .unwrap()
+        .clone();
+    log.sort();
+    assert_eq!(log, ["Hello Johny !", "Hello from a plugin !"]);
+    assert_eq!(startups.load(Ordering::Relaxed), 1);
+    assert_eq!(output.globals.get(HostValue::SINGLETON).unwrap().0, 7);
+}
+
+#[test]
+fn host_systems_keep_running_after_unloading() {
+    let startups = Arc::new(AtomicUsize::new(0));
+    let mut app = host_app(startups.clone());
+    let plugin = load_plugin(&mut app);
+
+    // Registered after the plugin's `String` system, they must not depend on its code
+    app.scheduler
+        .before("Johny".to_string(), |g: GlobalAccess| g.cancel_event());
+    let cancelled = Arc::new(Mutex::new(Vec::new()));
+    let record = cancelled.clone();
+    app.scheduler.on_type::<Cancelled<String>, _>(
+        move |_: GlobalAccess, event: &Cancelled<String>| {
+            record.lock().unwrap().push(event.0.to_string());
+        },
+    );
+    assert!(app.unload_plugin(plugin));
+
+    let output = app.run(Start).unwrap();
+    assert!(output
+        .globals
+        .get(Vec::<String>::SINGLETON)
+        .unwrap()
+        .is_empty());
+    assert_eq!(*cancelled.lock().unwrap(), ["Johny"]);
+    assert_eq!(startups.load(Ordering::Relaxed), 1);
+    assert_eq!(output.globals.get(HostValue::SINGLETON).unwrap().0, 7);
+}
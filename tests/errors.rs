@@ -0,0 +1,116 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use nano::{
+    access,
+    errors::{ErrorPolicy, SystemErrored},
+    events::EventQueue,
+    globals::{Globals, IntoSingletonKey},
+    systems::{GlobalAccess, Scheduler},
+};
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Start;
+
+#[test]
+fn errors_identify_the_system() {
+    let mut scheduler = Scheduler::builder()
+        .threads(2)
+        .error_policy(ErrorPolicy::Continue)
+        .build();
+    let ids: Vec<_> = (0..3)
+        .map(|n| {
+            scheduler
+                .on(Start, move |_: GlobalAccess| match n {
+                    1 => Ok(()),
+                    _ => Err(n),
+                })
+                .id()
+        })
+        .collect();
+
+    let Err(run_error) = scheduler.run(Start, Globals::new()) else {
+        panic!("The systems should have errored");
+    };
+    let errors = run_error.errors;
+    let mut failed: Vec<_> = errors.iter().map(|error| error.system).collect();
+    failed.sort();
+    assert_eq!(failed, [ids[0], ids[2]]);
+    // Closures of the same function share their name, only the id tells them apart
+    assert_eq!(errors[0].system_name, errors[1].system_name);
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Tick(u32);
+
+#[test]
+fn panics_are_reported_and_workers_stay_alive() {
+    let mut scheduler = Scheduler::builder()
+        .threads(2)
+        .error_policy(ErrorPolicy::Continue)
+        .build();
+    let ran = Arc::new(AtomicUsize::new(0));
+
+    scheduler.on(Start, |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        (0..10).for_each(|n| event_queue.push(Tick(n)));
+    });
+    scheduler.on_type::<Tick, _>(|_: GlobalAccess, tick: &Tick| {
+        if tick.0 % 2 == 1 {
+            panic!("Odd tick {}", tick.0);
+        }
+    });
+    let counter = ran.clone();
+    scheduler.on_type::<Tick, _>(move |_: GlobalAccess, _: &Tick| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+
+    let Err(run_error) = scheduler.run(Start, Globals::new()) else {
+        panic!("The systems should have panicked");
+    };
+    assert_eq!(ran.load(Ordering::Relaxed), 10);
+    assert_eq!(run_error.errors.len(), 5);
+    for error in &run_error.errors {
+        assert!(error.panic_message().unwrap().starts_with("Odd tick"));
+    }
+}
+
+#[test]
+fn errors_of_error_handlers_are_returned() {
+    let mut scheduler = Scheduler::builder()
+        .threads(2)
+        .error_policy(ErrorPolicy::Emit)
+        .build();
+    let handled = Arc::new(AtomicUsize::new(0));
+
+    scheduler.on(Start, |_: GlobalAccess| Err("start failed"));
+    let counter = handled.clone();
+    scheduler.on_type::<SystemErrored, _>(move |_: GlobalAccess, _: &SystemErrored| {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Err("handler failed")
+    });
+
+    let Err(run_error) = scheduler.run(Start, Globals::new()) else {
+        panic!("The error handler should have errored");
+    };
+    assert_eq!(handled.load(Ordering::Relaxed), 1);
+    assert_eq!(run_error.errors.len(), 1);
+    assert!(run_error.to_string().contains("handler failed"));
+}
+
+#[test]
+fn run_errors_convert_to_std_errors() {
+    fn run() -> Result<(), Box<dyn std::error::Error>> {
+        let mut scheduler = Scheduler::new();
+        scheduler.on(Start, |_: GlobalAccess| Err("start failed"));
+        scheduler.run(Start, Globals::new())?;
+        Ok(())
+    }
+
+    let error = run().unwrap_err();
+    assert!(error.to_string().starts_with("1 system(s) errored"));
+}
@@ -0,0 +1,190 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
+use nano::{
+    access,
+    app::App,
+    events::{EventQueue, EventReader, Events},
+    globals::{Globals, IntoSingletonKey, Singleton},
+    systems::{GlobalAccess, Scheduler},
+};
+
+fn read(reader: &EventReader<u32>, events: &Events<u32>) -> Vec<u32> {
+    reader.read(events).copied().collect()
+}
+
+#[test]
+fn readers_read_each_event_once_until_it_is_dropped() {
+    let mut events = Events::new();
+    let early = EventReader::new();
+    let late = EventReader::new();
+
+    events.send_batch([1, 2]);
+    assert_eq!(early.len(&events), 2);
+    assert_eq!(read(&early, &events), [1, 2]);
+    assert!(early.is_empty(&events));
+
+    events.update();
+    events.send(3);
+    assert_eq!(read(&early, &events), [3]);
+    assert_eq!(late.len(&events), 3);
+
+    // 1 and 2 are dropped, the late reader skips them
+    events.update();
+    assert_eq!(events.iter().copied().collect::<Vec<_>>(), [3]);
+    assert_eq!(late.len(&events), 1);
+    assert_eq!(read(&late, &events), [3]);
+    assert_eq!(read(&EventReader::new(), &events), [3]);
+
+    events.update();
+    assert!(events.is_empty());
+    events.send(4);
+    assert_eq!(read(&early, &events), [4]);
+
+    let cleared = EventReader::new();
+    events.send(5);
+    events.clear();
+    assert!(cleared.is_empty(&events));
+    events.send(6);
+    assert_eq!(read(&cleared, &events), [6]);
+    assert_eq!(read(&early, &events), [6]);
+    assert_eq!(read(&late, &events), [6]);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Stage {
+    Start,
+    Send,
+    Read,
+    ReadNextStep,
+    ReadTooLate,
+}
+
+type Log = Arc<Mutex<Vec<(&'static str, Vec<u32>)>>>;
+
+/// Records the events still buffered, a new reader sees all of them
+fn reader_system(
+    step: &'static str,
+    next: Option<Stage>,
+    log: &Log,
+) -> impl Fn(GlobalAccess) + Send + Sync {
+    let log = log.clone();
+    move |g: GlobalAccess| {
+        access! { g |
+            &events: Events::<u32>::SINGLETON,
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        log.lock()
+            .unwrap()
+            .push((step, read(&EventReader::new(), &events)));
+        if let Some(next) = next {
+            event_queue.push(next);
+        }
+    }
+}
+
+#[test]
+fn events_last_until_the_end_of_the_next_step() {
+    let mut app = App::new();
+    app.add_events::<u32>();
+    let log = Log::default();
+
+    app.scheduler.on(Stage::Start, |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        event_queue.push(Stage::Send);
+        event_queue.push(Stage::Read);
+    });
+    app.scheduler.on(Stage::Send, |g: GlobalAccess| {
+        access! { g |
+            &mut events: Events::<u32>::SINGLETON,
+        };
+        events.send(7);
+    });
+    // Both layers of `Read` end with a sync point, the event must outlive them
+    app.scheduler.before(Stage::Read, |_: GlobalAccess| {});
+    app.scheduler.on(
+        Stage::Read,
+        reader_system("same step", Some(Stage::ReadNextStep), &log),
+    );
+    app.scheduler.on(
+        Stage::ReadNextStep,
+        reader_system("next step", Some(Stage::ReadTooLate), &log),
+    );
+    app.scheduler
+        .on(Stage::ReadTooLate, reader_system("too late", None, &log));
+
+    let output = app.run(Stage::Start).unwrap();
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            ("same step", vec![7]),
+            ("next step", vec![7]),
+            ("too late", vec![]),
+        ]
+    );
+    assert!(output
+        .globals
+        .get(Events::<u32>::SINGLETON)
+        .unwrap()
+        .is_empty());
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct Tick;
+
+#[test]
+fn systems_read_each_event_once() {
+    const TICKS: u32 = 3;
+
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    let sent = Arc::new(AtomicU32::new(0));
+    let counter = sent.clone();
+    scheduler.before(Tick, move |g: GlobalAccess| {
+        access! { g |
+            &mut events: Events::<u32>::SINGLETON,
+        };
+        events.send(counter.fetch_add(1, Ordering::Relaxed) + 1);
+    });
+    let read: Vec<_> = (0..2)
+        .map(|_| {
+            let read = Arc::new(Mutex::new(Vec::<u32>::new()));
+            let record = read.clone();
+            scheduler.on(Tick, move |g: GlobalAccess| {
+                access! { g |
+                    &events: Events::<u32>::SINGLETON,
+                };
+                record.lock().unwrap().extend(events.read(&g));
+            });
+            read
+        })
+        .collect();
+    scheduler.after(Tick, move |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        if sent.load(Ordering::Relaxed) < TICKS {
+            event_queue.push(Tick);
+        }
+    });
+    scheduler.update_events::<u32>();
+
+    let mut globals = Globals::new();
+    globals.insert(Singleton(Events::<u32>::new()));
+    let output = scheduler.run(Tick, globals).unwrap();
+    for read in read {
+        assert_eq!(*read.lock().unwrap(), [1, 2, 3]);
+    }
+    // Outside of systems every buffered event is read, the last one is still there
+    let events = output.globals.get(Events::<u32>::SINGLETON).unwrap();
+    assert_eq!(
+        events
+            .read(&GlobalAccess::new(&output.globals))
+            .copied()
+            .collect::<Vec<_>>(),
+        [3]
+    );
+}
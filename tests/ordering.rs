@@ -0,0 +1,157 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use nano::{
+    access,
+    events::EventQueue,
+    globals::{Globals, IntoSingletonKey},
+    systems::{DispatchOrder, GlobalAccess, Scheduler},
+};
+
+#[derive(PartialEq, Eq, Hash)]
+struct Start;
+
+/// The order in which `SYSTEMS` systems bound to `Start` ran
+fn run_order(order: DispatchOrder) -> Vec<usize> {
+    const SYSTEMS: usize = 20;
+
+    let mut scheduler = Scheduler::builder().deterministic(order).build();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    for n in 0..SYSTEMS {
+        let ran = ran.clone();
+        scheduler.on(Start, move |_: GlobalAccess| ran.lock().unwrap().push(n));
+    }
+    scheduler.run(Start, Globals::new()).unwrap();
+
+    let ran = ran.lock().unwrap().clone();
+    ran
+}
+
+#[test]
+fn same_seed_gives_same_order() {
+    let shuffled = DispatchOrder::Shuffled { seed: 42 };
+    let order = run_order(shuffled);
+    assert_eq!(order, run_order(shuffled));
+    assert_ne!(order, run_order(DispatchOrder::Registration));
+
+    let mut sorted = order.clone();
+    sorted.sort();
+    assert_eq!(sorted, run_order(DispatchOrder::Registration));
+}
+
+#[test]
+fn registration_order_is_kept_inline() {
+    assert_eq!(
+        run_order(DispatchOrder::Registration),
+        (0..20).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn registration_order_is_kept_with_main_thread_systems() {
+    let mut scheduler = Scheduler::builder()
+        .deterministic(DispatchOrder::Registration)
+        .build();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    for n in 0..6 {
+        let ran = ran.clone();
+        let config = scheduler.on(Start, move |_: GlobalAccess| ran.lock().unwrap().push(n));
+        if n % 2 == 0 {
+            config.on_main_thread();
+        }
+    }
+    scheduler.run(Start, Globals::new()).unwrap();
+    assert_eq!(*ran.lock().unwrap(), [0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn ordered_systems_run_one_layer_after_the_other() {
+    for seed in 0..10 {
+        let mut scheduler = Scheduler::builder()
+            .threads(4)
+            .chaos(seed)
+            .chaos_max_delay(Duration::from_micros(200))
+            .build();
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            let ran = ran.clone();
+            move |g: GlobalAccess| {
+                // Gives the chaos mode a chance to delay the system
+                access! { g |
+                    &_event_queue: EventQueue::SINGLETON,
+                };
+                ran.lock().unwrap().push(name);
+            }
+        };
+        scheduler.on(Start, record("a")).label("a");
+        scheduler.on(Start, record("b")).after("a").label("b");
+        scheduler.on(Start, record("c")).before("a");
+        scheduler.on(Start, record("d")).after("b").after("a");
+        scheduler.run(Start, Globals::new()).unwrap();
+
+        let ran = ran.lock().unwrap();
+        let position = |name| ran.iter().position(|ran| *ran == name).unwrap();
+        assert_eq!(ran.len(), 4);
+        assert!(position("c") < position("a"), "seed {seed}: {ran:?}");
+        assert!(position("a") < position("b"), "seed {seed}: {ran:?}");
+        assert!(position("b") < position("d"), "seed {seed}: {ran:?}");
+    }
+}
+
+#[test]
+#[should_panic(expected = "creates a cycle")]
+fn cycles_are_rejected_at_registration() {
+    let mut scheduler = Scheduler::new();
+    scheduler.on(Start, |_: GlobalAccess| {}).label("a");
+    scheduler
+        .on(Start, |_: GlobalAccess| {})
+        .label("b")
+        .after("a");
+    scheduler
+        .on(Start, |_: GlobalAccess| {})
+        .before("b")
+        .after("b");
+}
+
+#[test]
+#[should_panic(expected = "creates a cycle")]
+fn cycles_through_typed_systems_are_rejected() {
+    let mut scheduler = Scheduler::new();
+    scheduler
+        .on(Start, |_: GlobalAccess| {})
+        .label("a")
+        .after("b");
+    scheduler
+        .on_type::<Start, _>(|_: GlobalAccess| {})
+        .label("b")
+        .after("a");
+}
+
+#[test]
+fn rejected_cycles_leave_the_scheduler_usable() {
+    let mut scheduler = Scheduler::new();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let record = |name: &'static str| {
+        let ran = ran.clone();
+        move |_: GlobalAccess| ran.lock().unwrap().push(name)
+    };
+    scheduler.on(Start, record("a")).label("a");
+    let id = scheduler.on(Start, record("b")).label("b").after("a").id();
+
+    let cycle = panic::catch_unwind(AssertUnwindSafe(|| {
+        scheduler
+            .on(Start, record("c"))
+            .label("c")
+            .before("a")
+            .after("b");
+    }));
+    assert!(cycle.is_err());
+    // The system without the rejected constraint stays registered
+    assert!(scheduler.is_enabled(id));
+
+    scheduler.run(Start, Globals::new()).unwrap();
+    assert_eq!(*ran.lock().unwrap(), ["c", "a", "b"]);
+}
@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use nano::{
+    access,
+    events::EventQueue,
+    globals::{Globals, IntoSingletonKey, Singleton},
+    systems::{GlobalAccess, Scheduler},
+};
+
+#[derive(PartialEq, Eq, Hash)]
+struct Tick;
+
+#[derive(PartialEq, Eq, Hash)]
+struct Count;
+
+/// Sums the per-thread `u64` counters into the `u64` singleton
+fn counting_scheduler() -> (Scheduler, Globals) {
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    scheduler.fold_at_sync(|total: &mut u64, count: u64| *total += count);
+    let mut globals = Globals::new();
+    globals.insert(Singleton(0u64));
+    globals.insert_per_thread(|| 0u64);
+    (scheduler, globals)
+}
+
+fn count(g: GlobalAccess) {
+    *g.per_thread::<u64>().unwrap() += 1;
+}
+
+#[test]
+fn counters_of_every_worker_are_summed() {
+    const SYSTEMS: u64 = 1_000;
+    const TICKS: usize = 5;
+
+    let (mut scheduler, globals) = counting_scheduler();
+    for _ in 0..SYSTEMS {
+        scheduler.on(Tick, count);
+    }
+    let ticks = AtomicUsize::new(1);
+    scheduler.on(Tick, move |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        if ticks.fetch_add(1, Ordering::Relaxed) < TICKS {
+            event_queue.push(Tick);
+        }
+    });
+
+    let output = scheduler.run(Tick, globals).unwrap();
+    let total = *output.globals.get(u64::SINGLETON).unwrap();
+    assert_eq!(total, SYSTEMS * TICKS as u64);
+}
+
+#[test]
+fn counters_are_folded_once() {
+    let (mut scheduler, globals) = counting_scheduler();
+    scheduler.on(Count, count);
+    scheduler.on(Count, |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        event_queue.push(Tick);
+        event_queue.push(Tick);
+    });
+    scheduler.on(Tick, |_: GlobalAccess| {});
+
+    let output = scheduler.run(Count, globals).unwrap();
+    assert_eq!(*output.globals.get(u64::SINGLETON).unwrap(), 1);
+}
@@ -0,0 +1,249 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use nano::{
+    access,
+    commands::ExitMode,
+    events::EventQueue,
+    globals::{Globals, IntoSingletonKey, Singleton},
+    locals::LocalGlobals,
+    runtime::ExitReason,
+    systems::{GlobalAccess, Scheduler},
+};
+
+#[derive(PartialEq, Eq, Hash)]
+struct Tick;
+
+/// Sets the flag once the globals holding it are dropped
+struct DropFlag(Arc<AtomicBool>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn dropping_a_runtime_stops_its_workers() {
+    let dropped = Arc::new(AtomicBool::new(false));
+    let mut globals = Globals::new();
+    globals.insert(Singleton(DropFlag(dropped.clone())));
+
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    scheduler.on(Tick, |_: GlobalAccess| {});
+    let mut runtime = scheduler.into_runtime(globals);
+    runtime.push_event(Tick);
+    runtime.step().unwrap();
+
+    // The workers share the globals, they are only dropped once every worker has exited
+    drop(runtime);
+    assert!(dropped.load(Ordering::SeqCst));
+}
+
+#[test]
+fn run_until_exit_waits_for_an_exit() {
+    let scheduler = Scheduler::new();
+    let sender = scheduler.event_sender();
+    let exit = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        sender.request_exit();
+    });
+
+    // The queue is empty right after the start event, the run must keep waiting
+    let start = Instant::now();
+    let output = scheduler.run_until_exit(Tick, Globals::new()).unwrap();
+    exit.join().unwrap();
+    assert_eq!(output.exit, ExitReason::Exit(ExitMode::Graceful));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    let output = Scheduler::new()
+        .run_until_exit_timeout(Tick, Globals::new(), Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(output.exit, ExitReason::TimedOut);
+}
+
+#[test]
+fn timeout_stops_events_that_keep_pushing_events() {
+    let mut scheduler = Scheduler::new();
+    scheduler.on(Tick, |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        event_queue.push(Tick);
+    });
+
+    let start = Instant::now();
+    let output = scheduler
+        .run_until_exit_timeout(Tick, Globals::new(), Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(output.exit, ExitReason::TimedOut);
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn main_thread_systems_share_the_locals_of_the_caller() {
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    scheduler
+        .on_startup(|g: GlobalAccess| {
+            let mut locals = g.locals().unwrap();
+            let greeting = locals.get::<Rc<str>>().unwrap().clone();
+            locals.insert(Rc::new(RefCell::new(vec![greeting])));
+            locals.insert(thread::current().id());
+        })
+        .on_main_thread();
+    scheduler
+        .on_startup(|g: GlobalAccess| {
+            // The access can be shared with other threads, the locals can't
+            thread::scope(|scope| {
+                scope.spawn(|| assert!(g.locals().is_none()));
+            });
+            assert!(g.locals().is_some());
+        })
+        .on_main_thread();
+    scheduler.on_startup(|g: GlobalAccess| assert!(g.locals().is_none()));
+
+    let mut locals = LocalGlobals::new();
+    locals.insert::<Rc<str>>("hello".into());
+    let output = scheduler
+        .run_with_locals(Tick, Globals::new(), locals)
+        .unwrap();
+
+    let greetings = output.locals.get::<Rc<RefCell<Vec<Rc<str>>>>>().unwrap();
+    assert_eq!(*greetings.borrow(), [Rc::from("hello")]);
+    assert_eq!(
+        output.locals.get::<ThreadId>(),
+        Some(&thread::current().id())
+    );
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct Ping(u32);
+
+#[test]
+fn event_senders_wake_an_idle_run() {
+    let mut scheduler = Scheduler::builder().threads(2).build();
+    let pinged = Arc::new(Mutex::new(Vec::new()));
+    let record = pinged.clone();
+    scheduler.on_type::<Ping, _>(move |_: GlobalAccess, ping: &Ping| {
+        record.lock().unwrap().push(ping.0);
+    });
+
+    let sender = scheduler.event_sender();
+    let network = thread::spawn(move || {
+        // The run is idle by then, waiting for events
+        thread::sleep(Duration::from_millis(50));
+        sender.send(Ping(1));
+        sender.send(Ping(2));
+        sender.request_exit();
+    });
+    let output = scheduler
+        .run_until_exit_timeout(Tick, Globals::new(), Duration::from_secs(10))
+        .unwrap();
+    network.join().unwrap();
+
+    assert_eq!(output.exit, ExitReason::Exit(ExitMode::Graceful));
+    let mut pinged = pinged.lock().unwrap().clone();
+    pinged.sort();
+    assert_eq!(pinged, [1, 2]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Batch {
+    Start,
+    Exit,
+    Next,
+    Later,
+}
+
+/// Runs `Start`, which pushes `Exit` then `Next`. `Exit` requests the exit and pushes `Later`
+fn run_exiting(exit_now: bool) -> (ExitReason, Vec<Batch>) {
+    let mut scheduler = Scheduler::builder().inline().build();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let record = ran.clone();
+    scheduler.on_type::<Batch, _>(move |g: GlobalAccess, batch: &Batch| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+            &mut commands: Scheduler::COMMANDS,
+        };
+        record.lock().unwrap().push(*batch);
+        match batch {
+            Batch::Start => {
+                event_queue.push(Batch::Exit);
+                event_queue.push(Batch::Next);
+            }
+            Batch::Exit => {
+                event_queue.push(Batch::Later);
+                if exit_now {
+                    commands.exit_now();
+                } else {
+                    commands.exit();
+                }
+            }
+            Batch::Next | Batch::Later => {}
+        }
+    });
+
+    let output = scheduler.run(Batch::Start, Globals::new()).unwrap();
+    let ran = ran.lock().unwrap().clone();
+    (output.exit, ran)
+}
+
+#[test]
+fn exit_finishes_the_current_batch() {
+    let (exit, ran) = run_exiting(false);
+    assert_eq!(exit, ExitReason::Exit(ExitMode::Graceful));
+    assert_eq!(ran, [Batch::Start, Batch::Exit, Batch::Next]);
+}
+
+#[test]
+fn exit_now_drops_the_remaining_events() {
+    let (exit, ran) = run_exiting(true);
+    assert_eq!(exit, ExitReason::Exit(ExitMode::Immediate));
+    assert_eq!(ran, [Batch::Start, Batch::Exit]);
+}
+
+struct Config(&'static str);
+
+#[test]
+fn shutdown_systems_run_after_an_immediate_exit() {
+    let mut scheduler = Scheduler::builder().threads(2).build();
+    let ran = Arc::new(Mutex::new(Vec::new()));
+
+    let record = ran.clone();
+    scheduler.on_startup(move |g: GlobalAccess| {
+        access! { g |
+            &mut commands: Globals::COMMANDS,
+        };
+        commands.insert(Singleton(Config("set at startup")));
+        record.lock().unwrap().push("startup");
+    });
+    let record = ran.clone();
+    scheduler.on(Tick, move |g: GlobalAccess| {
+        access! { g |
+            &config: Config::SINGLETON,
+            &mut commands: Scheduler::COMMANDS,
+        };
+        assert_eq!(config.0, "set at startup");
+        record.lock().unwrap().push("tick");
+        commands.exit_now();
+    });
+    let record = ran.clone();
+    scheduler.on_shutdown(move |g: GlobalAccess| {
+        access! { g |
+            &_config: Config::SINGLETON,
+        };
+        record.lock().unwrap().push("shutdown");
+    });
+
+    let output = scheduler.run(Tick, Globals::new()).unwrap();
+    assert_eq!(output.exit, ExitReason::Exit(ExitMode::Immediate));
+    assert_eq!(*ran.lock().unwrap(), ["startup", "tick", "shutdown"]);
+}
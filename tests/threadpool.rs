@@ -0,0 +1,82 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use nano::{
+    access,
+    events::EventQueue,
+    globals::{Globals, IntoSingletonKey},
+    systems::{GlobalAccess, Scheduler},
+};
+
+#[derive(PartialEq, Eq, Hash)]
+struct Tick;
+
+/// Same workload as `examples/throughput.rs`, scaled down
+#[test]
+fn thousands_of_systems_all_run() {
+    const SYSTEMS: usize = 5_000;
+    const TICKS: usize = 20;
+
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    let ran = Arc::new(AtomicUsize::new(0));
+    for _ in 0..SYSTEMS {
+        let ran = ran.clone();
+        scheduler.on(Tick, move |_: GlobalAccess| {
+            ran.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    let ticks = AtomicUsize::new(1);
+    scheduler.on(Tick, move |g: GlobalAccess| {
+        access! { g |
+            &mut event_queue: EventQueue::SINGLETON,
+        };
+        if ticks.fetch_add(1, Ordering::Relaxed) < TICKS {
+            event_queue.push(Tick);
+        }
+    });
+
+    let start = Instant::now();
+    scheduler.run(Tick, Globals::new()).unwrap();
+    let elapsed = start.elapsed();
+
+    assert_eq!(ran.load(Ordering::Relaxed), SYSTEMS * TICKS);
+    eprintln!(
+        "{} systems in {elapsed:?} ({:.0} systems/s)",
+        SYSTEMS * TICKS,
+        (SYSTEMS * TICKS) as f64 / elapsed.as_secs_f64()
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "single-threaded", ignore = "there are no workers")]
+fn idle_workers_steal_from_busy_ones() {
+    const SYSTEMS: usize = 400;
+
+    let mut scheduler = Scheduler::builder().threads(4).build();
+    let slow_threads = Arc::new(Mutex::new(Vec::<ThreadId>::new()));
+    // Jobs are handed out round robin so every slow system is queued on the same worker,
+    // the other workers can only run them by stealing once they are done with the fast ones
+    for n in 0..SYSTEMS {
+        let slow_threads = slow_threads.clone();
+        scheduler.on(Tick, move |_: GlobalAccess| {
+            if n % 4 == 0 {
+                thread::sleep(Duration::from_millis(1));
+                slow_threads.lock().unwrap().push(thread::current().id());
+            }
+        });
+    }
+
+    scheduler.run(Tick, Globals::new()).unwrap();
+
+    let mut slow_threads = slow_threads.lock().unwrap().clone();
+    assert_eq!(slow_threads.len(), SYSTEMS / 4);
+    slow_threads.sort_by_key(|id| format!("{id:?}"));
+    slow_threads.dedup();
+    assert!(slow_threads.len() > 1);
+}